@@ -1,9 +1,11 @@
 use includedir_codegen::{self, Compression};
 
 fn main() {
-    includedir_codegen::start("DB_SETUP")
-        .dir("sql", Compression::Gzip)
-        .build("db_setup.rs")
+    // Pick up newly added migrations, not only edits to existing ones
+    println!("cargo:rerun-if-changed=migrations");
+    includedir_codegen::start("MIGRATIONS")
+        .dir("migrations", Compression::Gzip)
+        .build("migrations.rs")
         .unwrap();
 //    includedir_codegen::start("STATIC_FILES")
 //        .dir("static", Compression::Gzip)
@@ -1,4 +1,6 @@
-#![recursion_limit = "128"]
+#![recursion_limit = "256"]
+
+use std::collections::HashMap;
 
 use failure::Error;
 use serde_derive::{Deserialize, Serialize};
@@ -6,8 +8,8 @@ use yew::{html, start_app, Component, ComponentLink, Html, Renderable, ShouldRen
 use yew::format::{Nothing, Json};
 use yew::services::fetch::{FetchService, FetchTask, Request, Response};
 
-const ALL_MOVIES: &str = "/api/all_movies";
-const MOVIE: &str = "/api/movie";
+const LIBRARIES: &str = "/api/libraries";
+const LOGIN: &str = "/api/login";
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 struct Movie {
@@ -20,13 +22,46 @@ struct Movie {
     pub actors: String,
     pub drawer: String,
     pub column: String,
+    /// Sent back as `If-Match` so stale edits are refused
+    #[serde(default)]
+    pub version: i32,
+    /// Lent out and so missing from its drawer
+    #[serde(default)]
+    pub on_loan: bool,
+}
+
+/// A library the logged in user can see
+#[derive(Debug, Clone, Deserialize)]
+struct Library {
+    pub id: String,
+    pub name: String,
+}
+
+/// Messages for each invalid field, keyed by field name
+type FieldErrors = HashMap<String, Vec<String>>;
+
+/// Error body returned by the API
+#[derive(Debug, Clone, Deserialize, Default)]
+struct ApiError {
+    pub code: String,
+    pub message: String,
+    #[serde(default)]
+    pub details: Option<FieldErrors>,
+}
+
+/// Login form contents
+#[derive(Debug, Clone, Serialize, Default)]
+struct Credentials {
+    pub username: String,
+    pub password: String,
 }
 
 #[derive(Debug, Clone)]
 enum Scene {
     Loading,
+    Login(Credentials, Option<String>),
     Main(Option<Vec<Movie>>),
-    AddMovie(Movie, CRUDType),
+    AddMovie(Movie, CRUDType, FieldErrors),
 }
 
 #[derive(Debug, Clone)]
@@ -40,17 +75,22 @@ struct Model {
     fetch_service: FetchService,
     ft: Option<FetchTask>,
     scene: Scene,
+    libraries: Vec<Library>,
+    /// Id of the library being shown
+    library: Option<String>,
 }
 
 #[derive(Debug)]
 enum Msg {
     Main,
     MainReady(Result<Vec<Movie>, Error>),
+    LibrariesReady(Vec<Library>),
+    ChooseLibrary(String),
     FetchError,
     AddMovie,
     UpdateMovie(String),
     UpdateMovieReady(Movie),
-    DeleteMovie(String),
+    DeleteMovie(String, i32),
     AddMovieEditTitle(String),
     AddMovieEditRating(String),
     AddMovieEditCategory(String),
@@ -60,6 +100,12 @@ enum Msg {
     AddMovieEditDrawer(String),
     AddMovieEditColumn(String),
     AddMovieSubmit,
+    AddMovieInvalid(FieldErrors),
+    NeedLogin,
+    LoginEditUsername(String),
+    LoginEditPassword(String),
+    LoginSubmit,
+    LoginFailed(String),
 }
 
 impl Component for Model {
@@ -72,8 +118,10 @@ impl Component for Model {
             fetch_service: FetchService::new(),
             ft: None,
             scene: Scene::Loading,
+            libraries: Vec::new(),
+            library: None,
         };
-        model.load_movies(ALL_MOVIES);
+        model.load_libraries();
         model
     }
 
@@ -83,10 +131,32 @@ impl Component for Model {
                 self.scene = Scene::Main(data.ok());
             }
             Msg::Main => {
-                self.load_movies(ALL_MOVIES);
+                if self.library.is_some() {
+                    self.load_movies();
+                } else {
+                    self.load_libraries();
+                }
+            }
+            Msg::LibrariesReady(libraries) => {
+                // Stay in the chosen library if it is still there
+                let chosen = self.library.take().filter(|id| {
+                    libraries.iter().any(|library| &library.id == id)
+                });
+                self.library =
+                    chosen.or_else(|| libraries.first().map(|library| library.id.clone()));
+                self.libraries = libraries;
+                if self.library.is_some() {
+                    self.load_movies();
+                } else {
+                    self.scene = Scene::Main(Some(Vec::new()));
+                }
+            }
+            Msg::ChooseLibrary(id) => {
+                self.library = Some(id);
+                self.load_movies();
             }
             Msg::AddMovie => {
-                self.scene = Scene::AddMovie(Default::default(), CRUDType::Create);
+                self.scene = Scene::AddMovie(Default::default(), CRUDType::Create, Default::default());
             }
             Msg::UpdateMovie(id) => {
                 let callback = self.link
@@ -99,16 +169,14 @@ impl Component for Model {
                             Msg::FetchError
                         }
                     });
-                let mut uri = String::new();
-                uri.push_str(MOVIE);
-                uri.push_str(&format!("?id={}", id));
+                let uri = format!("{}?id={}", self.library_url("movie"), id);
                 let request = Request::get(&uri)
                     .body(Nothing)
                     .expect("Failed to construct request");
                 let task = self.fetch_service.fetch(request, callback);
                 self.ft = Some(task);
             }
-            Msg::DeleteMovie(id) => {
+            Msg::DeleteMovie(id, version) => {
                 let callback = self.link
                     .send_back(move |response: Response<Result<String, Error>>| {
                         let (meta, _) = response.into_parts();
@@ -119,10 +187,9 @@ impl Component for Model {
                             Msg::FetchError
                         }
                     });
-                let mut uri = String::new();
-                uri.push_str(MOVIE);
-                uri.push_str(&format!("?id={}", id));
+                let uri = format!("{}?id={}", self.library_url("movie"), id);
                 let request = Request::delete(&uri)
+                    .header("If-Match", format!("\"{}\"", version))
                     .body(Nothing)
                     .expect("Failed to construct request");
                 let task = self.fetch_service.fetch(request, callback);
@@ -130,75 +197,127 @@ impl Component for Model {
 
             }
             Msg::AddMovieEditTitle(data) => {
-                if let Scene::AddMovie(movie, _) = &mut self.scene {
+                if let Scene::AddMovie(movie, _, _) = &mut self.scene {
                     movie.title = data;
                 }
             }
             Msg::AddMovieEditRating(data) => {
-                if let Scene::AddMovie(movie, _) = &mut self.scene {
+                if let Scene::AddMovie(movie, _, _) = &mut self.scene {
                     movie.rating = data;
                 }
             }
             Msg::AddMovieEditCategory(data) => {
-                if let Scene::AddMovie(movie, _) = &mut self.scene {
+                if let Scene::AddMovie(movie, _, _) = &mut self.scene {
                     movie.category = data;
                 }
             }
             Msg::AddMovieEditFormat(data) => {
-                if let Scene::AddMovie(movie, _) = &mut self.scene {
+                if let Scene::AddMovie(movie, _, _) = &mut self.scene {
                     movie.format = data;
                 }
             }
             Msg::AddMovieEditAspect(data) => {
-                if let Scene::AddMovie(movie, _) = &mut self.scene {
+                if let Scene::AddMovie(movie, _, _) = &mut self.scene {
                     movie.aspect = data;
                 }
             }
             Msg::AddMovieEditActors(data) => {
-                if let Scene::AddMovie(movie, _) = &mut self.scene {
+                if let Scene::AddMovie(movie, _, _) = &mut self.scene {
                     movie.actors = data;
                 }
             }
             Msg::AddMovieEditDrawer(data) => {
-                if let Scene::AddMovie(movie, _) = &mut self.scene {
+                if let Scene::AddMovie(movie, _, _) = &mut self.scene {
                     movie.drawer = data;
                 }
             }
             Msg::AddMovieEditColumn(data) => {
-                if let Scene::AddMovie(movie, _) = &mut self.scene {
+                if let Scene::AddMovie(movie, _, _) = &mut self.scene {
                     movie.column = data;
                 }
             }
             Msg::AddMovieSubmit => {
-                if let Scene::AddMovie(movie, crud_type) = &self.scene {
+                if let Scene::AddMovie(movie, crud_type, _) = &self.scene {
                     let callback = self.link
-                        .send_back(move |response: Response<Json<Result<(), Error>>>| {
-                            let (meta, _) = response.into_parts();
-                            println!("META: {:?}", meta);
+                        .send_back(move |response: Response<Json<Result<ApiError, Error>>>| {
+                            let (meta, Json(data)) = response.into_parts();
+                            println!("META: {:?}, {:?}", meta, data);
                             if meta.status.is_success() {
                                 Msg::Main
                             } else {
-                                Msg::FetchError
+                                match data {
+                                    Ok(ApiError { details: Some(errors), .. }) => {
+                                        Msg::AddMovieInvalid(errors)
+                                    }
+                                    _ => Msg::FetchError,
+                                }
                             }
                         });
+                    let uri = self.library_url("movie");
                     let mut builder = match crud_type {
-                        CRUDType::Create => Request::post(MOVIE),
-                        CRUDType::Update => Request::put(MOVIE),
+                        CRUDType::Create => Request::post(&uri),
+                        CRUDType::Update => Request::put(&uri),
                     };
                     let request = builder
                             .header("Content-Type", "application/json")
+                            .header("If-Match", format!("\"{}\"", movie.version))
                             .body(Json(&movie))
                             .expect("Failed to construct request");
                     let task = self.fetch_service.fetch(request, callback);
                     self.ft = Some(task);
                 }
             }
+            Msg::AddMovieInvalid(data) => {
+                if let Scene::AddMovie(_, _, errors) = &mut self.scene {
+                    *errors = data;
+                }
+            }
             Msg::UpdateMovieReady(movie) => {
-                self.scene = Scene::AddMovie(movie, CRUDType::Update);
+                self.scene = Scene::AddMovie(movie, CRUDType::Update, Default::default());
             }
             Msg::FetchError => {
                 println!("Fetch Error");
             }
+            Msg::NeedLogin => {
+                // Whoever logs in next may belong to other libraries
+                self.library = None;
+                self.scene = Scene::Login(Default::default(), None);
+            }
+            Msg::LoginEditUsername(data) => {
+                if let Scene::Login(credentials, _) = &mut self.scene {
+                    credentials.username = data;
+                }
+            }
+            Msg::LoginEditPassword(data) => {
+                if let Scene::Login(credentials, _) = &mut self.scene {
+                    credentials.password = data;
+                }
+            }
+            Msg::LoginSubmit => {
+                if let Scene::Login(credentials, _) = &self.scene {
+                    let callback = self.link
+                        .send_back(move |response: Response<Json<Result<ApiError, Error>>>| {
+                            let (meta, Json(data)) = response.into_parts();
+                            if meta.status.is_success() {
+                                Msg::Main
+                            } else {
+                                Msg::LoginFailed(data.map(|e| e.message).unwrap_or_default())
+                            }
+                        });
+                    let request = Request::post(LOGIN)
+                        .header("Content-Type", "application/json")
+                        .body(Json(&credentials))
+                        .expect("Failed to construct request");
+                    let task = self.fetch_service.fetch(request, callback);
+                    self.ft = Some(task);
+                }
+            }
+            Msg::LoginFailed(message) => {
+                if let Scene::Login(credentials, error) = &mut self.scene {
+                    credentials.password.clear();
+                    *error = Some(message);
+                }
+            }
         }
         true
     }
@@ -212,10 +331,12 @@ impl Renderable<Model> for Model {
                     <div>{ "Loading" }</div>
                 })
             }
+            Scene::Login(credentials, error) => view_page(view_login(credentials, error)),
             Scene::Main(movies) => {
                 if let Some(movies) = &movies {
                     view_page(html! {
                         <section class="list",>
+                            { view_libraries(&self.libraries, &self.library) }
                             { for movies.iter().enumerate().map(view_movie_title) }
                         </section>
                     })
@@ -227,93 +348,197 @@ impl Renderable<Model> for Model {
                     })
                 }
             }
-            Scene::AddMovie(movie, crud_type) => {
+            Scene::AddMovie(movie, crud_type, errors) => {
                 let title = match crud_type {
                     CRUDType::Create => "Add Movie",
                     CRUDType::Update => "Edit Movie",
                 };
-                view_page(view_edit_movie(movie, title))
+                view_page(view_edit_movie(movie, errors, title))
             }
         }
     }
 }
 
 impl Model {
-    fn load_movies(&mut self, url: &str) {
+    /// A path under the library being shown
+    fn library_url(&self, path: &str) -> String {
+        let library = self.library.as_ref().map(String::as_str).unwrap_or_default();
+        format!("{}/{}/{}", LIBRARIES, library, path)
+    }
+
+    fn load_libraries(&mut self) {
+        let callback = self.link
+            .send_back(move |response: Response<Json<Result<Vec<Library>, Error>>>| {
+                let (meta, Json(data)) = response.into_parts();
+                match data {
+                    Ok(libraries) if meta.status.is_success() => Msg::LibrariesReady(libraries),
+                    _ if meta.status.as_u16() == 401 => Msg::NeedLogin,
+                    _ => Msg::FetchError,
+                }
+            });
+        let request = Request::get(LIBRARIES).body(Nothing).unwrap();
+        let task = self.fetch_service.fetch(request, callback);
+        self.ft = Some(task);
+    }
+
+    fn load_movies(&mut self) {
+        let url = self.library_url("all_movies");
         let callback = self.link
             .send_back(move |response: Response<Json<Result<Vec<Movie>, Error>>>| {
                 let (meta, Json(data)) = response.into_parts();
                 println!("META: {:?}, {:?}", meta, data);
                 if meta.status.is_success() {
                     Msg::MainReady(data)
+                } else if meta.status.as_u16() == 401 {
+                    Msg::NeedLogin
                 } else {
                     Msg::FetchError
                 }
             });
-        let request = Request::get(url).body(Nothing).unwrap();
+        let request = Request::get(&url).body(Nothing).unwrap();
         let task = self.fetch_service.fetch(request, callback);
         self.ft = Some(task);
     }
 }
 
+/// Links to switch between libraries, when there is more than one
+fn view_libraries(libraries: &[Library], chosen: &Option<String>) -> Html<Model> {
+    if libraries.len() < 2 {
+        return html! { <nav class="libraries",></nav> };
+    }
+    html! {
+        <nav class="libraries",>
+            { for libraries.iter().map(|library| view_library_link(library, chosen)) }
+        </nav>
+    }
+}
+
+fn view_library_link(library: &Library, chosen: &Option<String>) -> Html<Model> {
+    let id = library.id.clone();
+    let class = if chosen.as_ref() == Some(&library.id) { "chosen" } else { "" };
+    html! {
+        <a class=class, onclick=|_| Msg::ChooseLibrary(id.clone()),>{ &library.name }</a>
+    }
+}
+
 fn view_movie_title((idx, movie): (usize, &Movie)) -> Html<Model> {
     // TODO Make this better
     let class = if idx % 2 == 0 { "even" } else { "odd" };
     let title = movie.title.clone();
     let id = movie.id.clone();
     let id2 = movie.id.clone();
+    let version = movie.version;
+    let on_loan = if movie.on_loan { "On loan" } else { "" };
     html! {
         <div class=class,>
             <p>{ title }</p>
+            <span class="on-loan",>{ on_loan }</span>
             <a onclick=|_| Msg::UpdateMovie(id.clone()),>{ "Edit" }</a>
-            <a onclick=|_| Msg::DeleteMovie(id2.clone()),>{ "Remove" }</a>
+            <a onclick=|_| Msg::DeleteMovie(id2.clone(), version),>{ "Remove" }</a>
         </div>
     }
 }
 
-fn view_edit_movie(movie: &Movie, title: &str) -> Html<Model> {
+fn view_edit_movie(movie: &Movie, errors: &FieldErrors, title: &str) -> Html<Model> {
     html! {
         <div class="padded",>
             <h2>{ title }</h2>
         <div class="add_movie",>
             <label>{ "Title" }</label>
-            <input type="text",
-                   value=&movie.title,
-                   oninput=|e| Msg::AddMovieEditTitle(e.value), />
+            <div>
+                <input type="text",
+                       value=&movie.title,
+                       oninput=|e| Msg::AddMovieEditTitle(e.value), />
+                { view_field_errors(errors, "title") }
+            </div>
             <label>{ "Rating" }</label>
-            <input type="text",
-                   value=&movie.rating,
-                   oninput=|e| Msg::AddMovieEditRating(e.value), />
+            <div>
+                <input type="text",
+                       value=&movie.rating,
+                       oninput=|e| Msg::AddMovieEditRating(e.value), />
+                { view_field_errors(errors, "rating") }
+            </div>
             <label>{ "Category" }</label>
-            <input type="text",
-                   value=&movie.category,
-                   oninput=|e| Msg::AddMovieEditCategory(e.value), />
+            <div>
+                <input type="text",
+                       value=&movie.category,
+                       oninput=|e| Msg::AddMovieEditCategory(e.value), />
+                { view_field_errors(errors, "category") }
+            </div>
             <label>{ "Format" }</label>
-            <input type="text",
-                   value=&movie.format,
-                   oninput=|e| Msg::AddMovieEditFormat(e.value), />
+            <div>
+                <input type="text",
+                       value=&movie.format,
+                       oninput=|e| Msg::AddMovieEditFormat(e.value), />
+                { view_field_errors(errors, "format") }
+            </div>
             <label>{ "Aspect" }</label>
-            <input type="text",
-                   value=&movie.aspect,
-                   oninput=|e| Msg::AddMovieEditAspect(e.value), />
+            <div>
+                <input type="text",
+                       value=&movie.aspect,
+                       oninput=|e| Msg::AddMovieEditAspect(e.value), />
+                { view_field_errors(errors, "aspect") }
+            </div>
             <label>{ "Actors" }</label>
-            <input type="text",
-                   value=&movie.actors,
-                   oninput=|e| Msg::AddMovieEditActors(e.value), />
+            <div>
+                <input type="text",
+                       value=&movie.actors,
+                       oninput=|e| Msg::AddMovieEditActors(e.value), />
+                { view_field_errors(errors, "actors") }
+            </div>
             <label>{ "Drawer" }</label>
-            <input type="text",
-                   value=&movie.drawer,
-                   oninput=|e| Msg::AddMovieEditDrawer(e.value), />
+            <div>
+                <input type="text",
+                       value=&movie.drawer,
+                       oninput=|e| Msg::AddMovieEditDrawer(e.value), />
+                { view_field_errors(errors, "drawer") }
+            </div>
             <label>{ "Column" }</label>
-            <input type="text",
-                   value=&movie.column,
-                   oninput=|e| Msg::AddMovieEditColumn(e.value), />
+            <div>
+                <input type="text",
+                       value=&movie.column,
+                       oninput=|e| Msg::AddMovieEditColumn(e.value), />
+                { view_field_errors(errors, "column") }
+            </div>
         </div>
         <button onclick=|_| Msg::AddMovieSubmit,>{ "Save" }</button>
         </div>
     }
 }
 
+fn view_login(credentials: &Credentials, error: &Option<String>) -> Html<Model> {
+    let error = error.clone().unwrap_or_default();
+    html! {
+        <div class="padded",>
+            <h2>{ "Log In" }</h2>
+        <div class="add_movie",>
+            <label>{ "Username" }</label>
+            <div>
+                <input type="text",
+                       value=&credentials.username,
+                       oninput=|e| Msg::LoginEditUsername(e.value), />
+            </div>
+            <label>{ "Password" }</label>
+            <div>
+                <input type="password",
+                       value=&credentials.password,
+                       oninput=|e| Msg::LoginEditPassword(e.value), />
+            </div>
+        </div>
+        <p class="field_errors",>{ error }</p>
+        <button onclick=|_| Msg::LoginSubmit,>{ "Log In" }</button>
+        </div>
+    }
+}
+
+fn view_field_errors(errors: &FieldErrors, field: &str) -> Html<Model> {
+    let messages = errors.get(field).cloned().unwrap_or_default();
+    html! {
+        <ul class="field_errors",>
+            { for messages.into_iter().map(|message| html! { <li>{ message }</li> }) }
+        </ul>
+    }
+}
 
 fn view_page(main: Html<Model>) -> Html<Model> {
     html! {
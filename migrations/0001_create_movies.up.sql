@@ -8,4 +8,4 @@ CREATE TABLE IF NOT EXISTS movies (
   movies_actors VARCHAR NOT NULL,
   movies_drawer VARCHAR NOT NULL,
   movies_column VARCHAR NOT NULL
-);
\ No newline at end of file
+);
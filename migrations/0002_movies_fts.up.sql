@@ -0,0 +1,27 @@
+-- Full-text index over titles and actors. The movie id is stored unindexed
+-- rather than using an external content table since VACUUM may renumber the
+-- implicit rowids of `movies`.
+CREATE VIRTUAL TABLE movies_fts USING fts5(
+  movies_id UNINDEXED,
+  movies_title,
+  movies_actors,
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO movies_fts (movies_id, movies_title, movies_actors)
+SELECT movies_id, movies_title, movies_actors FROM movies;
+
+CREATE TRIGGER movies_fts_insert AFTER INSERT ON movies BEGIN
+  INSERT INTO movies_fts (movies_id, movies_title, movies_actors)
+  VALUES (new.movies_id, new.movies_title, new.movies_actors);
+END;
+
+CREATE TRIGGER movies_fts_delete AFTER DELETE ON movies BEGIN
+  DELETE FROM movies_fts WHERE movies_id = old.movies_id;
+END;
+
+CREATE TRIGGER movies_fts_update AFTER UPDATE OF movies_id, movies_title, movies_actors ON movies BEGIN
+  DELETE FROM movies_fts WHERE movies_id = old.movies_id;
+  INSERT INTO movies_fts (movies_id, movies_title, movies_actors)
+  VALUES (new.movies_id, new.movies_title, new.movies_actors);
+END;
@@ -0,0 +1,3 @@
+DROP INDEX movie_cast_people;
+DROP TABLE movie_cast;
+DROP TABLE people;
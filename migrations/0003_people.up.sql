@@ -0,0 +1,66 @@
+CREATE TABLE people (
+  people_id VARCHAR PRIMARY KEY NOT NULL,
+  people_name VARCHAR NOT NULL UNIQUE COLLATE NOCASE
+);
+
+CREATE TABLE movie_cast (
+  movie_cast_movies_id VARCHAR NOT NULL REFERENCES movies (movies_id) ON DELETE CASCADE,
+  movie_cast_people_id VARCHAR NOT NULL REFERENCES people (people_id) ON DELETE CASCADE,
+  movie_cast_role VARCHAR NOT NULL DEFAULT 'actor',
+  movie_cast_character VARCHAR,
+  movie_cast_billing INTEGER NOT NULL,
+  PRIMARY KEY (movie_cast_movies_id, movie_cast_people_id, movie_cast_role)
+);
+
+CREATE INDEX movie_cast_people ON movie_cast (movie_cast_people_id);
+
+-- Split every comma separated actors string into one row per credit,
+-- collapsing runs of whitespace inside names
+CREATE TEMP TABLE actor_credits AS
+WITH RECURSIVE split (movie, billing, name, rest) AS (
+  SELECT movies_id, 0, '', movies_actors || ',' FROM movies
+  UNION ALL
+  SELECT movie,
+         billing + 1,
+         trim(substr(rest, 1, instr(rest, ',') - 1)),
+         substr(rest, instr(rest, ',') + 1)
+  FROM split
+  WHERE rest <> ''
+)
+SELECT movie,
+       billing,
+       replace(replace(replace(replace(replace(name, char(9), ' '), '  ', ' '), '  ', ' '), '  ', ' '), '  ', ' ') AS name
+FROM split
+WHERE name <> '';
+
+-- Each person keeps the spelling of their first credit
+INSERT INTO people (people_id, people_name)
+SELECT lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4' ||
+       substr(lower(hex(randomblob(2))), 2) || '-' ||
+       substr('89ab', 1 + abs(random()) % 4, 1) || substr(lower(hex(randomblob(2))), 2) || '-' ||
+       lower(hex(randomblob(6))),
+       name
+FROM (
+  SELECT name, min(rowid)
+  FROM actor_credits
+  GROUP BY name COLLATE NOCASE
+);
+
+INSERT OR IGNORE INTO movie_cast (movie_cast_movies_id, movie_cast_people_id, movie_cast_role, movie_cast_billing)
+SELECT movie, people_id, 'actor', billing
+FROM actor_credits
+JOIN people ON people_name = name;
+
+DROP TABLE actor_credits;
+
+-- Rewrite the display string from the deduplicated names
+UPDATE movies SET movies_actors = coalesce((
+  SELECT group_concat(people_name, ', ')
+  FROM (
+    SELECT people_name
+    FROM movie_cast
+    JOIN people ON people_id = movie_cast_people_id
+    WHERE movie_cast_movies_id = movies.movies_id AND movie_cast_role = 'actor'
+    ORDER BY movie_cast_billing
+  )
+), '');
@@ -0,0 +1,25 @@
+-- Put back the spellings the up migration replaced. Databases migrated before
+-- the originals were kept have nothing to put back.
+CREATE TABLE IF NOT EXISTS movies_enum_originals (
+  movies_enum_originals_movies_id VARCHAR PRIMARY KEY NOT NULL,
+  movies_enum_originals_rating VARCHAR NOT NULL,
+  movies_enum_originals_format VARCHAR NOT NULL,
+  movies_enum_originals_aspect VARCHAR NOT NULL
+);
+
+UPDATE movies SET
+  movies_rating = (
+    SELECT movies_enum_originals_rating FROM movies_enum_originals
+    WHERE movies_enum_originals_movies_id = movies_id
+  ),
+  movies_format = (
+    SELECT movies_enum_originals_format FROM movies_enum_originals
+    WHERE movies_enum_originals_movies_id = movies_id
+  ),
+  movies_aspect = (
+    SELECT movies_enum_originals_aspect FROM movies_enum_originals
+    WHERE movies_enum_originals_movies_id = movies_id
+  )
+WHERE movies_id IN (SELECT movies_enum_originals_movies_id FROM movies_enum_originals);
+
+DROP TABLE movies_enum_originals;
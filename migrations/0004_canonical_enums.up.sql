@@ -0,0 +1,112 @@
+-- Rewrite ratings, formats and aspects to the canonical spellings accepted by
+-- the API. Anything unrecognised becomes NR or Other, and every value this
+-- changes is kept in movies_enum_originals so it can be looked up or undone.
+
+-- Each accepted spelling from `db::types`, already normalized
+CREATE TEMP TABLE enum_spellings (
+  kind VARCHAR NOT NULL,
+  spelling VARCHAR NOT NULL,
+  canonical VARCHAR NOT NULL,
+  PRIMARY KEY (kind, spelling)
+);
+
+INSERT INTO enum_spellings VALUES
+  ('rating', 'g', 'G'),
+  ('rating', 'pg', 'PG'),
+  ('rating', 'pg13', 'PG-13'),
+  ('rating', 'r', 'R'),
+  ('rating', 'nc17', 'NC-17'),
+  ('rating', 'nr', 'NR'),
+  ('rating', 'notrated', 'NR'),
+  ('rating', 'unrated', 'NR'),
+  ('rating', 'tvy', 'TV-Y'),
+  ('rating', 'tvy7', 'TV-Y7'),
+  ('rating', 'tvg', 'TV-G'),
+  ('rating', 'tvpg', 'TV-PG'),
+  ('rating', 'tv14', 'TV-14'),
+  ('rating', 'tvma', 'TV-MA'),
+  ('format', 'dvd', 'DVD'),
+  ('format', 'bluray', 'Blu-ray'),
+  ('format', 'bd', 'Blu-ray'),
+  ('format', '4kuhd', '4K UHD'),
+  ('format', '4k', '4K UHD'),
+  ('format', 'uhd', '4K UHD'),
+  ('format', '4kbluray', '4K UHD'),
+  ('format', 'uhdbluray', '4K UHD'),
+  ('format', 'vhs', 'VHS'),
+  ('format', 'digital', 'Digital'),
+  ('format', 'digitalcopy', 'Digital'),
+  ('format', 'other', 'Other'),
+  ('aspect', 'widescreen', 'Widescreen'),
+  ('aspect', 'ws', 'Widescreen'),
+  ('aspect', 'anamorphic', 'Widescreen'),
+  ('aspect', 'anamorphicwidescreen', 'Widescreen'),
+  ('aspect', 'letterbox', 'Widescreen'),
+  ('aspect', 'fullscreen', 'Fullscreen'),
+  ('aspect', 'fs', 'Fullscreen'),
+  ('aspect', 'full', 'Fullscreen'),
+  ('aspect', 'panscan', 'Fullscreen'),
+  ('aspect', 'panandscan', 'Fullscreen'),
+  ('aspect', 'widescreenfullscreen', 'Widescreen & Fullscreen'),
+  ('aspect', 'both', 'Widescreen & Fullscreen'),
+  ('aspect', 'other', 'Other');
+
+-- Every stored value reduced as `db::types::normalize` does, one character
+-- at a time: letters and digits are kept in lowercase and the rest dropped.
+-- SQLite only knows ASCII punctuation, so other characters are kept, which
+-- leaves them unrecognised just as letters outside ASCII are in Rust.
+CREATE TEMP TABLE enum_values AS
+WITH RECURSIVE reduce (movie, kind, rest, normalized) AS (
+  SELECT movies_id, 'rating', movies_rating, '' FROM movies
+  UNION ALL
+  SELECT movies_id, 'format', movies_format, '' FROM movies
+  UNION ALL
+  SELECT movies_id, 'aspect', movies_aspect, '' FROM movies
+  UNION ALL
+  SELECT movie, kind, substr(rest, 2), normalized || CASE
+      WHEN lower(substr(rest, 1, 1)) BETWEEN 'a' AND 'z'
+        OR substr(rest, 1, 1) BETWEEN '0' AND '9'
+        OR unicode(substr(rest, 1, 1)) > 127
+      THEN lower(substr(rest, 1, 1))
+      ELSE ''
+    END
+  FROM reduce
+  WHERE rest <> ''
+)
+SELECT movie, kind, normalized FROM reduce WHERE rest = '';
+
+CREATE TABLE movies_enum_originals (
+  movies_enum_originals_movies_id VARCHAR PRIMARY KEY NOT NULL,
+  movies_enum_originals_rating VARCHAR NOT NULL,
+  movies_enum_originals_format VARCHAR NOT NULL,
+  movies_enum_originals_aspect VARCHAR NOT NULL
+);
+
+INSERT INTO movies_enum_originals
+SELECT movies_id, movies_rating, movies_format, movies_aspect FROM movies;
+
+UPDATE movies SET
+  movies_rating = coalesce((
+    SELECT canonical FROM enum_values JOIN enum_spellings USING (kind)
+    WHERE movie = movies_id AND kind = 'rating' AND spelling = normalized
+  ), 'NR'),
+  movies_format = coalesce((
+    SELECT canonical FROM enum_values JOIN enum_spellings USING (kind)
+    WHERE movie = movies_id AND kind = 'format' AND spelling = normalized
+  ), 'Other'),
+  movies_aspect = coalesce((
+    SELECT canonical FROM enum_values JOIN enum_spellings USING (kind)
+    WHERE movie = movies_id AND kind = 'aspect' AND spelling = normalized
+  ), 'Other');
+
+-- Only keep what was actually rewritten
+DELETE FROM movies_enum_originals WHERE EXISTS (
+  SELECT 1 FROM movies
+  WHERE movies_id = movies_enum_originals_movies_id
+    AND movies_rating = movies_enum_originals_rating
+    AND movies_format = movies_enum_originals_format
+    AND movies_aspect = movies_enum_originals_aspect
+);
+
+DROP TABLE enum_values;
+DROP TABLE enum_spellings;
@@ -0,0 +1,4 @@
+DROP INDEX movies_updated_at;
+DROP INDEX movies_created_at;
+ALTER TABLE movies DROP COLUMN movies_updated_at;
+ALTER TABLE movies DROP COLUMN movies_created_at;
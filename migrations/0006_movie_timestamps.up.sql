@@ -0,0 +1,12 @@
+-- SQLite cannot add a column with a non-constant default, so the columns start
+-- out empty and existing rows are stamped with the time of the migration.
+-- Times are UTC.
+ALTER TABLE movies ADD COLUMN movies_created_at TIMESTAMP NOT NULL DEFAULT '';
+ALTER TABLE movies ADD COLUMN movies_updated_at TIMESTAMP NOT NULL DEFAULT '';
+
+UPDATE movies
+SET movies_created_at = strftime('%Y-%m-%d %H:%M:%f', 'now'),
+    movies_updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now');
+
+CREATE INDEX movies_created_at ON movies (movies_created_at);
+CREATE INDEX movies_updated_at ON movies (movies_updated_at);
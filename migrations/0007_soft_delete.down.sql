@@ -0,0 +1,7 @@
+-- Anything still in the trash is deleted for good
+DELETE FROM movie_cast
+WHERE movie_cast_movies_id IN (SELECT movies_id FROM movies WHERE movies_deleted_at IS NOT NULL);
+DELETE FROM movies WHERE movies_deleted_at IS NOT NULL;
+
+DROP INDEX movies_deleted_at;
+ALTER TABLE movies DROP COLUMN movies_deleted_at;
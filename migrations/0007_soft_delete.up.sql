@@ -0,0 +1,4 @@
+-- Deleted movies stay in the table, with their cast, until purged
+ALTER TABLE movies ADD COLUMN movies_deleted_at TIMESTAMP;
+
+CREATE INDEX movies_deleted_at ON movies (movies_deleted_at);
@@ -0,0 +1,15 @@
+-- One row per write to a movie. There is deliberately no foreign key so the
+-- history outlives the movie once it is purged.
+CREATE TABLE audit_log (
+  audit_log_id INTEGER PRIMARY KEY AUTOINCREMENT,
+  audit_log_movies_id VARCHAR NOT NULL,
+  audit_log_action VARCHAR NOT NULL,
+  audit_log_actor VARCHAR NOT NULL,
+  audit_log_at TIMESTAMP NOT NULL,
+  -- JSON snapshots of the movie, NULL before a create and after a purge
+  audit_log_before TEXT,
+  audit_log_after TEXT
+);
+
+CREATE INDEX audit_log_movie ON audit_log (audit_log_movies_id, audit_log_at);
+CREATE INDEX audit_log_at ON audit_log (audit_log_at);
@@ -0,0 +1,43 @@
+-- Every version of every movie, as the JSON of the full row. Like the audit
+-- log there is no foreign key, so purged movies can still be brought back.
+CREATE TABLE movie_revisions (
+  movie_revisions_movies_id VARCHAR NOT NULL,
+  movie_revisions_version INTEGER NOT NULL,
+  movie_revisions_actor VARCHAR NOT NULL,
+  movie_revisions_at TIMESTAMP NOT NULL,
+  movie_revisions_movie TEXT NOT NULL,
+  PRIMARY KEY (movie_revisions_movies_id, movie_revisions_version)
+);
+
+CREATE TRIGGER movie_revisions_no_update BEFORE UPDATE ON movie_revisions
+BEGIN
+  SELECT RAISE(ABORT, 'movie revisions are immutable');
+END;
+
+CREATE TRIGGER movie_revisions_no_delete BEFORE DELETE ON movie_revisions
+BEGIN
+  SELECT RAISE(ABORT, 'movie revisions are immutable');
+END;
+
+-- The current state of each existing movie becomes its first known revision
+INSERT INTO movie_revisions
+SELECT movies_id,
+       movies_version,
+       'migration',
+       movies_updated_at,
+       json_object(
+         'id', movies_id,
+         'title', movies_title,
+         'rating', movies_rating,
+         'category', movies_category,
+         'format', movies_format,
+         'aspect', movies_aspect,
+         'actors', movies_actors,
+         'drawer', movies_drawer,
+         'column', movies_column,
+         'version', movies_version,
+         'created_at', replace(movies_created_at, ' ', 'T'),
+         'updated_at', replace(movies_updated_at, ' ', 'T'),
+         'deleted_at', replace(movies_deleted_at, ' ', 'T')
+       )
+FROM movies;
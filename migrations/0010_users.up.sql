@@ -0,0 +1,19 @@
+CREATE TABLE users (
+  users_id VARCHAR PRIMARY KEY NOT NULL,
+  users_username VARCHAR NOT NULL UNIQUE COLLATE NOCASE,
+  -- Argon2 encoded hash, including its parameters and salt
+  users_password_hash VARCHAR NOT NULL,
+  users_created_at TIMESTAMP NOT NULL
+);
+
+-- Only a SHA-256 of each session token is kept, so the table alone cannot be
+-- used to log in
+CREATE TABLE sessions (
+  sessions_token_hash VARCHAR PRIMARY KEY NOT NULL,
+  sessions_users_id VARCHAR NOT NULL REFERENCES users (users_id) ON DELETE CASCADE,
+  sessions_created_at TIMESTAMP NOT NULL,
+  sessions_expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX sessions_user ON sessions (sessions_users_id);
+CREATE INDEX sessions_expires_at ON sessions (sessions_expires_at);
@@ -0,0 +1,5 @@
+ALTER TABLE users ADD COLUMN users_role VARCHAR NOT NULL DEFAULT 'viewer';
+
+-- Everyone could do everything before roles existed, so existing accounts
+-- keep that
+UPDATE users SET users_role = 'admin';
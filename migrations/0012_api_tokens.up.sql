@@ -0,0 +1,15 @@
+-- Long lived tokens for scripts. As with sessions only a SHA-256 of the
+-- token is kept.
+CREATE TABLE api_tokens (
+  api_tokens_id VARCHAR PRIMARY KEY NOT NULL,
+  api_tokens_users_id VARCHAR NOT NULL REFERENCES users (users_id) ON DELETE CASCADE,
+  api_tokens_name VARCHAR NOT NULL,
+  api_tokens_token_hash VARCHAR NOT NULL UNIQUE,
+  api_tokens_read_only BOOLEAN NOT NULL DEFAULT 0,
+  api_tokens_created_at TIMESTAMP NOT NULL,
+  -- Never expires when null
+  api_tokens_expires_at TIMESTAMP,
+  api_tokens_last_used_at TIMESTAMP
+);
+
+CREATE INDEX api_tokens_user ON api_tokens (api_tokens_users_id);
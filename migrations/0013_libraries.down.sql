@@ -0,0 +1,9 @@
+DROP INDEX audit_log_library;
+DROP INDEX movies_library;
+
+ALTER TABLE movie_revisions DROP COLUMN movie_revisions_libraries_id;
+ALTER TABLE audit_log DROP COLUMN audit_log_libraries_id;
+ALTER TABLE movies DROP COLUMN movies_libraries_id;
+
+DROP TABLE library_members;
+DROP TABLE libraries;
@@ -0,0 +1,36 @@
+-- Each household keeps its movies in its own library. Users see the
+-- libraries they are members of.
+CREATE TABLE libraries (
+  libraries_id VARCHAR PRIMARY KEY NOT NULL,
+  libraries_name VARCHAR NOT NULL,
+  libraries_created_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE library_members (
+  library_members_libraries_id VARCHAR NOT NULL
+    REFERENCES libraries (libraries_id) ON DELETE CASCADE,
+  library_members_users_id VARCHAR NOT NULL REFERENCES users (users_id) ON DELETE CASCADE,
+  library_members_added_at TIMESTAMP NOT NULL,
+  PRIMARY KEY (library_members_libraries_id, library_members_users_id)
+);
+
+CREATE INDEX library_members_user ON library_members (library_members_users_id);
+
+-- Everything so far was one shared collection, which becomes the `home`
+-- library with every existing user in it
+INSERT INTO libraries
+VALUES ('home', 'Home', strftime('%Y-%m-%d %H:%M:%f', 'now'));
+
+INSERT INTO library_members
+SELECT 'home', users_id, strftime('%Y-%m-%d %H:%M:%f', 'now') FROM users;
+
+-- The audit log and revisions outlive purged movies, so they keep the
+-- library too. SQLite will not add a REFERENCES column with a default, so
+-- the link is not enforced.
+ALTER TABLE movies ADD COLUMN movies_libraries_id VARCHAR NOT NULL DEFAULT 'home';
+ALTER TABLE audit_log ADD COLUMN audit_log_libraries_id VARCHAR NOT NULL DEFAULT 'home';
+ALTER TABLE movie_revisions
+  ADD COLUMN movie_revisions_libraries_id VARCHAR NOT NULL DEFAULT 'home';
+
+CREATE INDEX movies_library ON movies (movies_libraries_id);
+CREATE INDEX audit_log_library ON audit_log (audit_log_libraries_id);
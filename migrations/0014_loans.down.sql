@@ -0,0 +1,3 @@
+ALTER TABLE movies DROP COLUMN movies_on_loan;
+
+DROP TABLE loans;
@@ -0,0 +1,19 @@
+-- Discs lent to friends. A loan is open until it has a return date, and a
+-- movie has at most one open loan.
+CREATE TABLE loans (
+  loans_id VARCHAR PRIMARY KEY NOT NULL,
+  loans_movies_id VARCHAR NOT NULL REFERENCES movies (movies_id) ON DELETE CASCADE,
+  loans_borrower VARCHAR NOT NULL,
+  -- A phone number or email address, free form
+  loans_contact VARCHAR NOT NULL DEFAULT '',
+  loans_lent_at TIMESTAMP NOT NULL,
+  loans_due_date DATE NOT NULL,
+  loans_returned_at TIMESTAMP
+);
+
+CREATE INDEX loans_movie ON loans (loans_movies_id);
+CREATE UNIQUE INDEX loans_open ON loans (loans_movies_id) WHERE loans_returned_at IS NULL;
+CREATE INDEX loans_due ON loans (loans_due_date) WHERE loans_returned_at IS NULL;
+
+-- Kept in step with the open loan so movie lists need no join
+ALTER TABLE movies ADD COLUMN movies_on_loan BOOLEAN NOT NULL DEFAULT 0;
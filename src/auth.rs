@@ -0,0 +1,267 @@
+use actix::MailboxError;
+use actix_web::http::{header, Method};
+use actix_web::middleware::{Middleware, Started};
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, ResponseError, Result};
+use futures::future::{self, Future};
+
+use crate::db::libraries::OpenLibrary;
+use crate::db::model::{Library, User};
+use crate::db::tokens::{AuthenticateToken, TokenOwner};
+use crate::db::users::Authenticate;
+use crate::db::{DbError, Role};
+use crate::handlers::AppState;
+
+/// Name of the cookie holding the session token
+pub const SESSION_COOKIE: &str = "moviedb_session";
+
+/// API paths that can be used without logging in
+const PUBLIC_PATHS: &[&str] = &["/api/login"];
+
+/// API paths, and everything under them, that any logged in user can use
+/// whatever the method
+const SELF_SERVICE_PATHS: &[&str] = &["/api/logout", "/api/tokens"];
+
+/// Everything under here manages the server itself
+const ADMIN_PREFIX: &str = "/api/admin";
+
+/// Followed by a library id, under which everything is that library's
+const LIBRARY_PREFIX: &str = "/api/libraries/";
+
+/// Kept from before there were libraries, and scoped to the user's own one
+const UNSCOPED_PATHS: &[&str] = &["/api/all_movies", "/api/movies"];
+
+fn is_under(path: &str, prefix: &str) -> bool {
+    path == prefix || path.starts_with(&format!("{}/", prefix))
+}
+
+fn is_read(method: &Method) -> bool {
+    *method == Method::GET || *method == Method::HEAD || *method == Method::OPTIONS
+}
+
+/// The least role allowed to make a request: reading needs viewer, changing
+/// needs editor, and deleting or administering the server needs admin
+pub fn required_role(method: &Method, path: &str) -> Role {
+    if SELF_SERVICE_PATHS.iter().any(|prefix| is_under(path, prefix)) {
+        return Role::Viewer;
+    }
+    if is_under(path, ADMIN_PREFIX) {
+        return Role::Admin;
+    }
+    if is_read(method) {
+        Role::Viewer
+    } else if *method == Method::DELETE {
+        Role::Admin
+    } else {
+        Role::Editor
+    }
+}
+
+/// The token from an `Authorization: Bearer` header, if there is one
+fn bearer_token(req: &HttpRequest<AppState>) -> Option<Result<String, DbError>> {
+    let value = req.headers().get(header::AUTHORIZATION)?;
+    let token = value
+        .to_str()
+        .ok()
+        .and_then(|value| {
+            let mut parts = value.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("bearer") => {
+                    Some(token.trim().to_string())
+                }
+                _ => None,
+            }
+        })
+        .ok_or_else(|| {
+            DbError::Unauthorized("The Authorization header must be `Bearer <token>`".to_string())
+        });
+    Some(token)
+}
+
+/// The logged in user, stored in the request's extensions by `RequireLogin`
+#[derive(Debug, Clone)]
+pub struct CurrentUser(pub User);
+
+/// The user a request was authenticated as, if any
+pub fn current_user(req: &HttpRequest<AppState>) -> Option<User> {
+    req.extensions()
+        .get::<CurrentUser>()
+        .map(|current| current.0.clone())
+}
+
+/// The library named in the path, stored by `RequireLogin` once it has
+/// checked that the user may use it
+#[derive(Debug, Clone)]
+pub struct CurrentLibrary(pub Library);
+
+impl CurrentLibrary {
+    pub fn id(&self) -> String {
+        self.0.id.clone()
+    }
+}
+
+impl FromRequest<AppState> for CurrentLibrary {
+    type Config = ();
+    type Result = Result<Self, Error>;
+
+    fn from_request(req: &HttpRequest<AppState>, _: &Self::Config) -> Self::Result {
+        req.extensions()
+            .get::<CurrentLibrary>()
+            .cloned()
+            .ok_or_else(|| DbError::NotFound("No library in the path".to_string()).into())
+    }
+}
+
+/// The library id in a path under `LIBRARY_PREFIX`
+fn library_in_path(path: &str) -> Option<String> {
+    if !path.starts_with(LIBRARY_PREFIX) {
+        return None;
+    }
+    let library = path[LIBRARY_PREFIX.len()..].split('/').next()?;
+    if library.is_empty() {
+        None
+    } else {
+        Some(library.to_string())
+    }
+}
+
+/// Check that what a request does is allowed for whoever made it
+fn authorize(owner: TokenOwner, required: Role, read: bool) -> Result<User, DbError> {
+    if owner.read_only && !read {
+        return Err(DbError::Forbidden("This API token is read-only".to_string()));
+    }
+    if !owner.user.role.allows(required) {
+        return Err(DbError::Forbidden(format!(
+            "This needs the {} role, you are {}",
+            required, owner.user.role
+        )));
+    }
+    Ok(owner.user)
+}
+
+/// Turn away API requests without a valid session cookie or API token with
+/// a 401, those the user's role or a read-only token does not allow with a
+/// 403, and those for a library the user is not a member of with a 404
+pub struct RequireLogin;
+
+impl Middleware<AppState> for RequireLogin {
+    fn start(&self, req: &HttpRequest<AppState>) -> Result<Started> {
+        if PUBLIC_PATHS.contains(&req.path()) {
+            return Ok(Started::Done);
+        }
+        let db = &req.state().db;
+        let owner: Box<dyn Future<Item = Result<TokenOwner, DbError>, Error = MailboxError>> =
+            match (bearer_token(req), req.cookie(SESSION_COOKIE)) {
+                (Some(Err(e)), _) => return Ok(Started::Response(e.error_response())),
+                (Some(Ok(token)), _) => Box::new(db.send(AuthenticateToken { token })),
+                (None, Some(cookie)) => Box::new(
+                    db.send(Authenticate {
+                        token: cookie.value().to_string(),
+                    })
+                    .map(|res| {
+                        res.map(|user| TokenOwner {
+                            user,
+                            read_only: false,
+                        })
+                    }),
+                ),
+                (None, None) => {
+                    return Ok(Started::Response(
+                        DbError::Unauthorized("Log in to use the API".to_string())
+                            .error_response(),
+                    ))
+                }
+            };
+
+        let required = required_role(req.method(), req.path());
+        let read = is_read(req.method());
+        let library = library_in_path(req.path());
+        let unscoped = UNSCOPED_PATHS.contains(&req.path());
+        let req = req.clone();
+        let authenticated = owner.from_err().and_then(move |res| {
+            let user = match res.and_then(|owner| authorize(owner, required, read)) {
+                Ok(user) => user,
+                Err(e) => return future::Either::A(future::ok(Some(e.error_response()))),
+            };
+            req.extensions_mut().insert(CurrentUser(user.clone()));
+            if library.is_none() && !unscoped {
+                return future::Either::A(future::ok(None));
+            }
+            future::Either::B(open_library(req, library, user))
+        });
+        Ok(Started::Future(Box::new(authenticated)))
+    }
+}
+
+/// Check membership of the library in the path, or of the user's own one
+fn open_library(
+    req: HttpRequest<AppState>,
+    library: Option<String>,
+    user: User,
+) -> Box<dyn Future<Item = Option<HttpResponse>, Error = Error>> {
+    let opened = req
+        .state()
+        .db
+        .send(OpenLibrary { library, user })
+        .from_err()
+        .map(move |res| match res {
+            Ok(library) => {
+                req.extensions_mut().insert(CurrentLibrary(library));
+                None
+            }
+            Err(e) => Some(e.error_response()),
+        });
+    Box::new(opened)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_needs_viewer() {
+        for method in &[Method::GET, Method::HEAD, Method::OPTIONS] {
+            assert_eq!(required_role(method, "/api/libraries/home/movies"), Role::Viewer);
+        }
+    }
+
+    #[test]
+    fn writing_needs_editor_and_deleting_admin() {
+        let path = "/api/libraries/home/movie";
+        assert_eq!(required_role(&Method::POST, path), Role::Editor);
+        assert_eq!(required_role(&Method::PUT, path), Role::Editor);
+        assert_eq!(required_role(&Method::PATCH, path), Role::Editor);
+        assert_eq!(required_role(&Method::DELETE, path), Role::Admin);
+    }
+
+    #[test]
+    fn admin_paths_need_admin_even_to_read() {
+        assert_eq!(required_role(&Method::GET, "/api/admin"), Role::Admin);
+        assert_eq!(required_role(&Method::GET, "/api/admin/users"), Role::Admin);
+        // Only whole path segments count
+        assert_eq!(required_role(&Method::GET, "/api/administer"), Role::Viewer);
+    }
+
+    #[test]
+    fn self_service_paths_need_only_viewer() {
+        assert_eq!(required_role(&Method::POST, "/api/logout"), Role::Viewer);
+        assert_eq!(required_role(&Method::POST, "/api/tokens"), Role::Viewer);
+        assert_eq!(required_role(&Method::DELETE, "/api/tokens/abc"), Role::Viewer);
+    }
+
+    #[test]
+    fn library_comes_from_the_first_segment_after_the_prefix() {
+        assert_eq!(
+            library_in_path("/api/libraries/home/movies"),
+            Some("home".to_string())
+        );
+        assert_eq!(library_in_path("/api/libraries/home"), Some("home".to_string()));
+    }
+
+    #[test]
+    fn paths_outside_a_library_have_none() {
+        assert_eq!(library_in_path("/api/libraries/"), None);
+        assert_eq!(library_in_path("/api/libraries"), None);
+        assert_eq!(library_in_path("/api/all_movies"), None);
+        assert_eq!(library_in_path("/api/admin/libraries/home/members"), None);
+    }
+}
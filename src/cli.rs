@@ -0,0 +1,394 @@
+use std::path::PathBuf;
+use std::time::Duration as StdDuration;
+
+use chrono::Duration;
+use diesel::sqlite::SqliteConnection;
+use structopt::StructOpt;
+
+use crate::db::libraries::DEFAULT_LIBRARY;
+use crate::db::now;
+use crate::db::pool::{ConnectionOptions, JournalMode, Synchronous};
+use crate::export::ExportFormat;
+use crate::validation::{Columns, DEFAULT_COLUMNS};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "movie_db", about = "Self-hosted database for a physical movie collection")]
+pub struct Opt {
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Run the HTTP server
+    #[structopt(name = "serve")]
+    Serve(ServeOpt),
+    /// Apply or revert schema migrations
+    #[structopt(name = "migrate")]
+    Migrate(MigrateOpt),
+    /// Permanently delete movies that have been in the trash too long
+    #[structopt(name = "purge")]
+    Purge(PurgeOpt),
+    /// Add movies from a CSV file, all or nothing
+    #[structopt(name = "import")]
+    Import(ImportOpt),
+    /// Write movies to a CSV, JSON or NDJSON file
+    #[structopt(name = "export")]
+    Export(ExportOpt),
+    /// Take a consistent snapshot of the database
+    #[structopt(name = "backup")]
+    Backup(BackupOpt),
+    /// Replace the database with a backup; stop the server first
+    #[structopt(name = "restore")]
+    Restore(RestoreOpt),
+    /// Create an account, reading its password from MOVIEDB_PASSWORD or stdin
+    #[structopt(name = "create-admin")]
+    CreateAdmin(CreateAdminOpt),
+}
+
+/// Options shared by every command that touches the database
+#[derive(Debug, StructOpt)]
+pub struct DbOpt {
+    /// Path to the SQLite database file
+    #[structopt(
+        short = "d",
+        long = "database",
+        env = "MOVIEDB_DATABASE",
+        default_value = "movies.db"
+    )]
+    pub database: String,
+
+    /// SQLite journal mode: delete, truncate, persist, memory, wal or off
+    #[structopt(long = "journal-mode", env = "MOVIEDB_JOURNAL_MODE", default_value = "wal")]
+    pub journal_mode: JournalMode,
+
+    /// Milliseconds to wait for another connection's write before giving up
+    #[structopt(long = "busy-timeout", env = "MOVIEDB_BUSY_TIMEOUT", default_value = "5000")]
+    pub busy_timeout: u64,
+
+    /// Enforce foreign keys, `on` or `off`
+    #[structopt(
+        long = "foreign-keys",
+        env = "MOVIEDB_FOREIGN_KEYS",
+        default_value = "on",
+        parse(try_from_str = "parse_switch")
+    )]
+    pub foreign_keys: bool,
+
+    /// SQLite synchronous setting: off, normal, full or extra
+    #[structopt(long = "synchronous", env = "MOVIEDB_SYNCHRONOUS", default_value = "normal")]
+    pub synchronous: Synchronous,
+}
+
+impl DbOpt {
+    pub fn connection_options(&self) -> ConnectionOptions {
+        ConnectionOptions {
+            journal_mode: self.journal_mode,
+            busy_timeout: StdDuration::from_millis(self.busy_timeout),
+            foreign_keys: self.foreign_keys,
+            synchronous: self.synchronous,
+        }
+    }
+
+    /// Open a connection with the configured pragmas, exiting on failure
+    pub fn establish(&self) -> SqliteConnection {
+        self.connection_options()
+            .establish(&self.database)
+            .unwrap_or_else(|e| panic!("Error connecting to {}: {}", self.database, e))
+    }
+}
+
+/// Where backups are kept and how many
+#[derive(Debug, Clone, StructOpt)]
+pub struct BackupDirOpt {
+    /// Directory backups are written to
+    #[structopt(
+        long = "backup-dir",
+        env = "MOVIEDB_BACKUP_DIR",
+        default_value = "backups",
+        parse(from_os_str)
+    )]
+    pub dir: PathBuf,
+
+    /// Number of backups to keep, older ones are deleted
+    #[structopt(long = "backup-keep", env = "MOVIEDB_BACKUP_KEEP", default_value = "7")]
+    pub keep: usize,
+}
+
+/// How login sessions behave
+#[derive(Debug, Clone, StructOpt)]
+pub struct SessionOpt {
+    /// How long a login lasts, such as `30d` or `12h`
+    #[structopt(
+        long = "session-ttl",
+        env = "MOVIEDB_SESSION_TTL",
+        default_value = "30d",
+        parse(try_from_str = "parse_ttl")
+    )]
+    pub ttl: Duration,
+
+    /// Mark the session cookie `Secure` so browsers only send it over HTTPS;
+    /// turn `off` only when serving plain HTTP behind no proxy
+    #[structopt(
+        long = "secure-cookies",
+        env = "MOVIEDB_SECURE_COOKIES",
+        default_value = "on",
+        parse(try_from_str = "parse_switch")
+    )]
+    pub secure: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ServeOpt {
+    #[structopt(flatten)]
+    pub db: DbOpt,
+
+    /// Address to bind the HTTP server to
+    #[structopt(
+        short = "a",
+        long = "address",
+        env = "MOVIEDB_ADDRESS",
+        default_value = "127.0.0.1"
+    )]
+    pub address: String,
+
+    /// Port to bind the HTTP server to
+    #[structopt(short = "p", long = "port", env = "MOVIEDB_PORT", default_value = "8080")]
+    pub port: u16,
+
+    /// Directory the frontend is served from
+    #[structopt(
+        long = "static-dir",
+        env = "MOVIEDB_STATIC_DIR",
+        default_value = "./static",
+        parse(from_os_str)
+    )]
+    pub static_dir: PathBuf,
+
+    /// Number of database worker threads
+    #[structopt(long = "workers", env = "MOVIEDB_WORKERS", default_value = "3")]
+    pub workers: usize,
+
+    /// Maximum number of pooled database connections
+    #[structopt(long = "pool-size", env = "MOVIEDB_POOL_SIZE", default_value = "10")]
+    pub pool_size: u32,
+
+    #[structopt(flatten)]
+    pub sessions: SessionOpt,
+
+    #[structopt(flatten)]
+    pub backups: BackupDirOpt,
+
+    /// Largest CSV upload accepted by the import endpoint, such as `10M`
+    #[structopt(
+        long = "max-import-size",
+        env = "MOVIEDB_MAX_IMPORT_SIZE",
+        default_value = "10M",
+        parse(try_from_str = "parse_size")
+    )]
+    pub max_import_size: usize,
+
+    /// Shelf columns movies may be placed in, separated by commas
+    #[structopt(
+        long = "columns",
+        env = "MOVIEDB_COLUMNS",
+        raw(default_value = "DEFAULT_COLUMNS")
+    )]
+    pub columns: Columns,
+
+    /// Also back up on a schedule, such as every `1d` or `6h`
+    #[structopt(
+        long = "backup-every",
+        env = "MOVIEDB_BACKUP_EVERY",
+        parse(try_from_str = "parse_age")
+    )]
+    pub backup_every: Option<Duration>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MigrateOpt {
+    #[structopt(flatten)]
+    pub db: DbOpt,
+
+    /// Print the SQL that would run without touching the database
+    #[structopt(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Schema version to migrate up or down to, defaults to the latest
+    #[structopt(long = "target")]
+    pub target: Option<i32>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct PurgeOpt {
+    #[structopt(flatten)]
+    pub db: DbOpt,
+
+    /// How long a movie stays in the trash, such as `30d`, `2w` or `12h`
+    #[structopt(
+        long = "older-than",
+        env = "MOVIEDB_PURGE_AGE",
+        default_value = "30d",
+        parse(try_from_str = "parse_age")
+    )]
+    pub older_than: Duration,
+
+    /// List what would be deleted without deleting it
+    #[structopt(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ImportOpt {
+    #[structopt(flatten)]
+    pub db: DbOpt,
+
+    /// CSV file with a header row
+    #[structopt(parse(from_os_str))]
+    pub file: PathBuf,
+
+    /// Id of the library to add the movies to
+    #[structopt(long = "library", raw(default_value = "DEFAULT_LIBRARY"))]
+    pub library: String,
+
+    /// Read a field from a differently named column, such as `title=Name`;
+    /// may be repeated
+    #[structopt(short = "m", long = "map", number_of_values = 1)]
+    pub map: Vec<String>,
+
+    /// Check every row and report what would be imported without saving
+    #[structopt(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Shelf columns movies may be placed in, separated by commas
+    #[structopt(
+        long = "columns",
+        env = "MOVIEDB_COLUMNS",
+        raw(default_value = "DEFAULT_COLUMNS")
+    )]
+    pub columns: Columns,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ExportOpt {
+    #[structopt(flatten)]
+    pub db: DbOpt,
+
+    /// File to write, replacing it if it exists
+    #[structopt(parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// Id of the library to export
+    #[structopt(long = "library", raw(default_value = "DEFAULT_LIBRARY"))]
+    pub library: String,
+
+    /// `csv`, `json` or `ndjson`, guessed from the file extension when not
+    /// given
+    #[structopt(short = "f", long = "format")]
+    pub format: Option<ExportFormat>,
+
+    /// Only export movies matching these `/api/libraries/{lib}/movies`
+    /// filters, written as a query string such as `rating=PG&sort=drawer`
+    #[structopt(long = "filter", default_value = "")]
+    pub filter: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct BackupOpt {
+    #[structopt(flatten)]
+    pub db: DbOpt,
+
+    #[structopt(flatten)]
+    pub backups: BackupDirOpt,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RestoreOpt {
+    #[structopt(flatten)]
+    pub db: DbOpt,
+
+    /// Backup file to restore
+    #[structopt(parse(from_os_str))]
+    pub file: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CreateAdminOpt {
+    #[structopt(flatten)]
+    pub db: DbOpt,
+
+    /// Name to log in with
+    pub username: String,
+}
+
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("expected on or off, not `{}`", value)),
+    }
+}
+
+/// Parse a size in bytes, optionally with a unit of `K`, `M` or `G`
+fn parse_size(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<usize>()
+        .map_err(|_| format!("invalid size `{}`, expected something like 10M", value))?;
+    let unit_bytes: usize = match unit.to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("unknown unit `{}`, expected K, M or G", unit)),
+    };
+    number
+        .checked_mul(unit_bytes)
+        .ok_or_else(|| format!("size `{}` is too large", value))
+}
+
+/// Parse an age given as a number with a unit of `w`, `d`, `h` or `m`;
+/// a bare number is in days
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<i64>()
+        .map_err(|_| format!("invalid age `{}`, expected something like 30d", value))?;
+    let unit_seconds = match unit {
+        "w" => 7 * 24 * 60 * 60,
+        "" | "d" => 24 * 60 * 60,
+        "h" => 60 * 60,
+        "m" => 60,
+        _ => return Err(format!("unknown unit `{}`, expected w, d, h or m", unit)),
+    };
+    // `Duration` panics past its range rather than saturating
+    number
+        .checked_mul(unit_seconds)
+        .filter(|seconds| *seconds <= Duration::max_value().num_seconds())
+        .map(Duration::seconds)
+        .ok_or_else(|| format!("age `{}` is too large", value))
+}
+
+impl ServeOpt {
+    pub fn bind_addr(&self) -> String {
+        format!("{}:{}", self.address, self.port)
+    }
+}
+
+/// Parse a session length like `parse_age`, turning away ones that would
+/// expire past the last time a timestamp can hold
+fn parse_ttl(value: &str) -> Result<Duration, String> {
+    let ttl = parse_age(value)?;
+    match now().checked_add_signed(ttl) {
+        Some(_) => Ok(ttl),
+        None => Err(format!("session length `{}` is too long", value.trim())),
+    }
+}
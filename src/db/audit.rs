@@ -0,0 +1,99 @@
+use ::actix::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+use super::schema::audit_log::dsl::*;
+use super::types::{AuditAction, Json};
+use super::{deserialize_time, model, now, page_size, revisions, DbError, DbExecutor};
+
+/// A movie as JSON for storing in a text column
+pub fn snapshot(movie: &model::Movie) -> QueryResult<Json> {
+    serde_json::to_value(movie)
+        .map(Json)
+        .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))
+}
+
+/// Record a write to `movie`. `before` is `None` for a create and `after` is
+/// `None` for a purge. The movie as it is after the write is also kept as a
+/// revision.
+pub fn record(
+    conn: &SqliteConnection,
+    action: AuditAction,
+    actor: &str,
+    movie: &model::Movie,
+    before: Option<&model::Movie>,
+    after: Option<&model::Movie>,
+) -> QueryResult<()> {
+    diesel::insert_into(audit_log)
+        .values((
+            audit_log_movies_id.eq(&movie.id),
+            audit_log_action.eq(action),
+            audit_log_actor.eq(actor),
+            audit_log_at.eq(now()),
+            audit_log_before.eq(before.map(snapshot).transpose()?),
+            audit_log_after.eq(after.map(snapshot).transpose()?),
+            audit_log_libraries_id.eq(&movie.library_id),
+        ))
+        .execute(conn)?;
+    if let Some(movie) = after {
+        revisions::save(conn, movie, actor)?;
+    }
+    Ok(())
+}
+
+/*
+ * Read the audit log
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GetAudit {
+    #[serde(skip)]
+    pub library: String,
+    /// Only writes to this movie
+    pub movie: Option<String>,
+    /// At or after this date or time
+    #[serde(default, deserialize_with = "deserialize_time")]
+    pub after: Option<NaiveDateTime>,
+    /// Before this date or time
+    #[serde(default, deserialize_with = "deserialize_time")]
+    pub before: Option<NaiveDateTime>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl Message for GetAudit {
+    type Result = Result<Vec<model::AuditEntry>, DbError>;
+}
+
+impl Handler<GetAudit> for DbExecutor {
+    type Result = Result<Vec<model::AuditEntry>, DbError>;
+
+    fn handle(&mut self, msg: GetAudit, _: &mut Self::Context) -> Self::Result {
+        let limit = page_size(msg.limit)?;
+        let offset = msg.offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(DbError::validation("offset must not be negative"));
+        }
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let mut query = audit_log
+            .filter(audit_log_libraries_id.eq(msg.library))
+            .order((audit_log_at.desc(), audit_log_id.desc()))
+            .limit(limit)
+            .offset(offset)
+            .into_boxed();
+        if let Some(ref movie) = msg.movie {
+            query = query.filter(audit_log_movies_id.eq(movie));
+        }
+        if let Some(value) = msg.after {
+            query = query.filter(audit_log_at.ge(value));
+        }
+        if let Some(value) = msg.before {
+            query = query.filter(audit_log_at.lt(value));
+        }
+        let entries = query.load::<model::AuditEntry>(conn)?;
+
+        Ok(entries)
+    }
+}
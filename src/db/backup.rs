@@ -0,0 +1,269 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ::actix::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use log::*;
+use serde_derive::{Deserialize, Serialize};
+
+use super::migrations::{self, Step};
+use super::{now, DbError, DbExecutor};
+
+/// Backups are named `movies-<UTC time>.db`; rotation only ever touches
+/// files of that shape
+const BACKUP_PREFIX: &str = "movies-";
+const BACKUP_SUFFIX: &str = ".db";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.3f";
+
+/// A snapshot in the backup directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub file: String,
+    pub size: u64,
+    pub created_at: NaiveDateTime,
+}
+
+fn io_error(action: &str, path: &Path, e: io::Error) -> DbError {
+    DbError::Database(format!("Unable to {} {}: {}", action, path.display(), e))
+}
+
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "''"))
+}
+
+/// Write a consistent copy of the open database to `dest`, which must not
+/// exist yet. `VACUUM INTO` reads inside a single transaction, so writers
+/// carrying on meanwhile are not captured half way.
+pub fn copy_to(conn: &SqliteConnection, dest: &Path) -> Result<(), DbError> {
+    conn.batch_execute(&format!("VACUUM INTO {}", quote(dest)))?;
+    Ok(())
+}
+
+/// Back up into `dir` under a timestamped name, then delete the oldest
+/// backups so that at most `keep` remain
+pub fn snapshot(conn: &SqliteConnection, dir: &Path, keep: usize) -> Result<BackupInfo, DbError> {
+    if keep == 0 {
+        return Err(DbError::validation("At least one backup must be kept"));
+    }
+    fs::create_dir_all(dir).map_err(|e| io_error("create", dir, e))?;
+
+    let file = format!(
+        "{}{}{}",
+        BACKUP_PREFIX,
+        now().format(BACKUP_TIME_FORMAT),
+        BACKUP_SUFFIX
+    );
+    let created_at = backup_time(&file).expect("backup names parse back");
+    let path = dir.join(&file);
+    copy_to(conn, &path)?;
+    let size = fs::metadata(&path)
+        .map_err(|e| io_error("read", &path, e))?
+        .len();
+    info!("Backed up database to {}", path.display());
+
+    for old in rotate(dir, keep)? {
+        info!("Removed old backup {}", old.display());
+    }
+    Ok(BackupInfo {
+        file,
+        size,
+        created_at,
+    })
+}
+
+/// Backups in `dir`, oldest first
+pub fn list(dir: &Path) -> Result<Vec<BackupInfo>, DbError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error("read", dir, e)),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| io_error("read", dir, e))?;
+        let file = entry.file_name().to_string_lossy().into_owned();
+        let created_at = match backup_time(&file) {
+            Some(created_at) => created_at,
+            None => continue,
+        };
+        let size = entry
+            .metadata()
+            .map_err(|e| io_error("read", &entry.path(), e))?
+            .len();
+        backups.push(BackupInfo {
+            file,
+            size,
+            created_at,
+        });
+    }
+    backups.sort_by_key(|backup| backup.created_at);
+    Ok(backups)
+}
+
+fn backup_time(file: &str) -> Option<NaiveDateTime> {
+    if !file.starts_with(BACKUP_PREFIX) || !file.ends_with(BACKUP_SUFFIX) {
+        return None;
+    }
+    let time = &file[BACKUP_PREFIX.len()..file.len() - BACKUP_SUFFIX.len()];
+    NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok()
+}
+
+/// Delete all but the newest `keep` backups in `dir`, returning what was
+/// removed
+pub fn rotate(dir: &Path, keep: usize) -> Result<Vec<PathBuf>, DbError> {
+    let backups = list(dir)?;
+    let excess = backups.len().saturating_sub(keep);
+    let mut removed = Vec::new();
+    for backup in &backups[..excess] {
+        let path = dir.join(&backup.file);
+        fs::remove_file(&path).map_err(|e| io_error("remove", &path, e))?;
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+/// What `restore` did
+#[derive(Debug)]
+pub struct Restored {
+    /// Schema version of the backup as it was taken
+    pub version: i32,
+    /// Migrations run to bring it up to date
+    pub migrated: Vec<Step>,
+    /// Copy of the database that was replaced, if there was one
+    pub previous: Option<PathBuf>,
+}
+
+/// Replace the database at `database` with the backup at `source`.
+///
+/// The backup is copied and checked first: it must pass SQLite's integrity
+/// check and carry a schema version this build knows, and older versions are
+/// migrated forward. Only then is the current database saved alongside as
+/// `<database>.pre-restore` and the copy moved into its place. Nothing else
+/// may have the database open while this runs.
+pub fn restore(source: &Path, database: &Path) -> Result<Restored, DbError> {
+    if !source.is_file() {
+        return Err(DbError::NotFound(format!("No backup at {}", source.display())));
+    }
+    let staged = sibling(database, "restore");
+    let _ = fs::remove_file(&staged);
+    fs::copy(source, &staged).map_err(|e| io_error("copy", source, e))?;
+
+    let checked = check(&staged, source);
+    let (version, migrated) = match checked {
+        Ok(checked) => checked,
+        Err(e) => {
+            let _ = fs::remove_file(&staged);
+            return Err(e);
+        }
+    };
+
+    let previous = if database.exists() {
+        let previous = sibling(database, "pre-restore");
+        let _ = fs::remove_file(&previous);
+        let conn = establish(database)?;
+        copy_to(&conn, &previous)?;
+        Some(previous)
+    } else {
+        None
+    };
+
+    fs::rename(&staged, database).map_err(|e| io_error("replace", database, e))?;
+    for journal in &["wal", "shm"] {
+        let _ = fs::remove_file(format!("{}-{}", database.display(), journal));
+    }
+    Ok(Restored {
+        version,
+        migrated,
+        previous,
+    })
+}
+
+/// Validate a staged copy of `source` and migrate it to the latest schema
+fn check(path: &Path, source: &Path) -> Result<(i32, Vec<Step>), DbError> {
+    let conn = establish(path)?;
+    let integrity = diesel::select(sql::<Text>(
+        "(SELECT group_concat(quick_check, '; ') FROM pragma_quick_check)",
+    ))
+    .get_result::<String>(&conn)
+    .map_err(|_| {
+        DbError::validation(format!("{} is not a SQLite database", source.display()))
+    })?;
+    if integrity != "ok" {
+        return Err(DbError::validation(format!(
+            "The backup is damaged: {}",
+            integrity
+        )));
+    }
+
+    let version = migrations::current_version(&conn)?;
+    let latest = migrations::latest_version();
+    if version == 0 {
+        return Err(DbError::validation(
+            "The backup has no schema version, it is not a movie database",
+        ));
+    }
+    if version > latest {
+        return Err(DbError::validation(format!(
+            "The backup is at schema version {} but this build only knows up to {}",
+            version, latest
+        )));
+    }
+    let migrated = migrations::run_pending(&conn)
+        .map_err(|e| DbError::Database(format!("Unable to migrate the backup: {}", e)))?;
+    Ok((version, migrated))
+}
+
+fn establish(path: &Path) -> Result<SqliteConnection, DbError> {
+    SqliteConnection::establish(&path.to_string_lossy()).map_err(|e| {
+        DbError::Database(format!("Error connecting to {}: {}", path.display(), e))
+    })
+}
+
+fn sibling(database: &Path, suffix: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", database.display(), suffix))
+}
+
+/*
+ * Back up from the server
+ */
+#[derive(Debug, Clone)]
+pub struct BackupDatabase {
+    pub dir: PathBuf,
+    pub keep: usize,
+}
+
+impl Message for BackupDatabase {
+    type Result = Result<BackupInfo, DbError>;
+}
+
+impl Handler<BackupDatabase> for DbExecutor {
+    type Result = Result<BackupInfo, DbError>;
+
+    fn handle(&mut self, msg: BackupDatabase, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        snapshot(conn, &msg.dir, msg.keep)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ListBackups {
+    pub dir: PathBuf,
+}
+
+impl Message for ListBackups {
+    type Result = Result<Vec<BackupInfo>, DbError>;
+}
+
+impl Handler<ListBackups> for DbExecutor {
+    type Result = Result<Vec<BackupInfo>, DbError>;
+
+    fn handle(&mut self, msg: ListBackups, _: &mut Self::Context) -> Self::Result {
+        list(&msg.dir)
+    }
+}
@@ -0,0 +1,150 @@
+use std::error::Error;
+use std::fmt;
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use diesel::r2d2::PoolError;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use log::*;
+use serde_derive::Serialize;
+use serde_json::{json, Value};
+
+/// Errors returned by `DbExecutor` handlers.
+///
+/// Each variant maps onto an HTTP status and is rendered as a JSON body of the
+/// form `{"code": ..., "message": ..., "details": ...}`.
+#[derive(Debug)]
+pub enum DbError {
+    NotFound(String),
+    Validation {
+        message: String,
+        details: Option<Value>,
+    },
+    Conflict(String),
+    /// No valid session or credentials came with the request
+    Unauthorized(String),
+    /// The user is logged in but their role does not allow the request
+    Forbidden(String),
+    /// The client's `If-Match` no longer names the current version
+    PreconditionFailed(String),
+    /// A write was sent without `If-Match`
+    PreconditionRequired(String),
+    /// An upload is bigger than the server accepts
+    PayloadTooLarge(String),
+    Database(String),
+    PoolExhausted(String),
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: &'a str,
+    details: Option<&'a Value>,
+}
+
+impl DbError {
+    pub fn validation<S: Into<String>>(message: S) -> Self {
+        DbError::Validation {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// A validation error for a single field, shaped like the ones
+    /// `validation::Validator` collects
+    pub fn field<S: Into<String>>(field: &str, message: S) -> Self {
+        let message = message.into();
+        DbError::Validation {
+            message: "1 field(s) failed validation".to_string(),
+            details: Some(json!({ field: [message] })),
+        }
+    }
+
+    /// Machine readable error code sent to clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            DbError::NotFound(_) => "not_found",
+            DbError::Validation { .. } => "validation_failed",
+            DbError::Conflict(_) => "conflict",
+            DbError::Unauthorized(_) => "unauthorized",
+            DbError::Forbidden(_) => "insufficient_role",
+            DbError::PreconditionFailed(_) => "precondition_failed",
+            DbError::PreconditionRequired(_) => "precondition_required",
+            DbError::PayloadTooLarge(_) => "payload_too_large",
+            DbError::Database(_) => "database_error",
+            DbError::PoolExhausted(_) => "pool_exhausted",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            DbError::NotFound(message)
+            | DbError::Validation { message, .. }
+            | DbError::Conflict(message)
+            | DbError::Unauthorized(message)
+            | DbError::Forbidden(message)
+            | DbError::PreconditionFailed(message)
+            | DbError::PreconditionRequired(message)
+            | DbError::PayloadTooLarge(message)
+            | DbError::Database(message)
+            | DbError::PoolExhausted(message) => message,
+        }
+    }
+
+    pub fn details(&self) -> Option<&Value> {
+        match self {
+            DbError::Validation { details, .. } => details.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl Error for DbError {}
+
+impl ResponseError for DbError {
+    fn error_response(&self) -> HttpResponse {
+        let status = match self {
+            DbError::NotFound(_) => StatusCode::NOT_FOUND,
+            DbError::Validation { .. } => StatusCode::BAD_REQUEST,
+            DbError::Conflict(_) => StatusCode::CONFLICT,
+            DbError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            DbError::Forbidden(_) => StatusCode::FORBIDDEN,
+            DbError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            DbError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            DbError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            DbError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            DbError::PoolExhausted(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        if status.is_server_error() {
+            error!("{}", self);
+        }
+        HttpResponse::build(status).json(ErrorBody {
+            code: self.code(),
+            message: self.message(),
+            details: self.details(),
+        })
+    }
+}
+
+impl From<DieselError> for DbError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => DbError::NotFound("Record not found".to_string()),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                DbError::Conflict(info.message().to_string())
+            }
+            e => DbError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<PoolError> for DbError {
+    fn from(e: PoolError) -> Self {
+        DbError::PoolExhausted(format!("No database connection available: {}", e))
+    }
+}
@@ -0,0 +1,49 @@
+use ::actix::prelude::*;
+use diesel::prelude::*;
+
+use super::{model, DbError, DbExecutor, MovieFilter};
+
+/// Movies fetched per round trip while exporting
+pub const EXPORT_CHUNK_SIZE: i64 = 200;
+
+/// The filtered movies of a library that come after `after`, or from the
+/// start, at most `EXPORT_CHUNK_SIZE` of them
+pub fn chunk(
+    conn: &SqliteConnection,
+    library: &str,
+    filter: &MovieFilter,
+    after: Option<&model::Movie>,
+) -> Result<Vec<model::Movie>, DbError> {
+    let query = match after {
+        Some(last) => filter.sorted_after(library, last),
+        None => filter.sorted(library),
+    };
+    let items = query.limit(EXPORT_CHUNK_SIZE).load::<model::Movie>(conn)?;
+
+    Ok(items)
+}
+
+/*
+ * Export movies a chunk at a time
+ */
+#[derive(Debug, Clone)]
+pub struct ExportMovies {
+    pub library: String,
+    pub filter: MovieFilter,
+    /// The last movie of the previous chunk
+    pub after: Option<model::Movie>,
+}
+
+impl Message for ExportMovies {
+    type Result = Result<Vec<model::Movie>, DbError>;
+}
+
+impl Handler<ExportMovies> for DbExecutor {
+    type Result = Result<Vec<model::Movie>, DbError>;
+
+    fn handle(&mut self, msg: ExportMovies, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        chunk(conn, &msg.library, &msg.filter, msg.after.as_ref())
+    }
+}
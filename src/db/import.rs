@@ -0,0 +1,113 @@
+use ::actix::prelude::*;
+use diesel::connection::TransactionManager;
+use diesel::prelude::*;
+use serde_derive::Serialize;
+use serde_json::{json, Value};
+
+use super::{create_movie, model, CreateMovie, DbError, DbExecutor};
+
+/// One data row of an import file, already checked by the validation layer
+#[derive(Debug)]
+pub struct Row {
+    /// Line number in the file, counting the header as line 1
+    pub line: u64,
+    pub movie: Result<CreateMovie, DbError>,
+}
+
+/// What an import did, or would do for a dry run
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub rows: usize,
+    /// Rows that added a movie
+    pub created: usize,
+    /// Rows naming an id whose identical movie was already saved
+    pub unchanged: usize,
+    pub movies: Vec<model::Movie>,
+}
+
+fn row_error(line: u64, e: &DbError) -> Value {
+    json!({
+        "line": line,
+        "code": e.code(),
+        "message": e.message(),
+        "details": e.details(),
+    })
+}
+
+/// Create every row in one transaction.
+///
+/// Each row is attempted even after an earlier one fails so that every
+/// problem is reported at once, but nothing is kept unless all rows succeed.
+/// A dry run goes through the same steps and then rolls back.
+pub fn import(
+    conn: &SqliteConnection,
+    rows: Vec<Row>,
+    dry_run: bool,
+) -> Result<ImportReport, DbError> {
+    let transactions = conn.transaction_manager();
+    transactions.begin_transaction_sql(conn, "BEGIN IMMEDIATE")?;
+
+    let mut report = ImportReport {
+        dry_run,
+        rows: rows.len(),
+        created: 0,
+        unchanged: 0,
+        movies: Vec::new(),
+    };
+    let mut errors = Vec::new();
+    for row in rows {
+        match row.movie.and_then(|movie| create_movie(conn, &movie)) {
+            Ok(created) => {
+                if created.created {
+                    report.created += 1;
+                } else {
+                    report.unchanged += 1;
+                }
+                report.movies.push(created.movie);
+            }
+            Err(e) => errors.push(row_error(row.line, &e)),
+        }
+    }
+
+    if !errors.is_empty() {
+        transactions.rollback_transaction(conn)?;
+        return Err(DbError::Validation {
+            message: format!(
+                "{} of {} rows failed, nothing was imported",
+                errors.len(),
+                report.rows
+            ),
+            details: Some(json!({ "rows": errors })),
+        });
+    }
+    if dry_run {
+        transactions.rollback_transaction(conn)?;
+    } else {
+        transactions.commit_transaction(conn)?;
+    }
+    Ok(report)
+}
+
+/*
+ * Import movies
+ */
+#[derive(Debug)]
+pub struct ImportMovies {
+    pub rows: Vec<Row>,
+    pub dry_run: bool,
+}
+
+impl Message for ImportMovies {
+    type Result = Result<ImportReport, DbError>;
+}
+
+impl Handler<ImportMovies> for DbExecutor {
+    type Result = Result<ImportReport, DbError>;
+
+    fn handle(&mut self, msg: ImportMovies, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        import(conn, msg.rows, msg.dry_run)
+    }
+}
@@ -0,0 +1,247 @@
+use ::actix::prelude::*;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use super::schema::libraries::dsl::*;
+use super::schema::library_members::dsl::*;
+use super::schema::users;
+use super::types::Role;
+use super::{model, now, write_transaction, DbError, DbExecutor};
+
+/// The library that movies from before libraries existed were put in
+pub const DEFAULT_LIBRARY: &str = "home";
+
+fn not_found(library: &str) -> DbError {
+    DbError::NotFound(format!("No library with id {}", library))
+}
+
+pub fn find_library(conn: &SqliteConnection, library: &str) -> Result<model::Library, DbError> {
+    libraries
+        .filter(libraries_id.eq(library))
+        .first::<model::Library>(conn)
+        .optional()?
+        .ok_or_else(|| not_found(library))
+}
+
+/*
+ * The libraries a user can see
+ */
+#[derive(Debug, Clone)]
+pub struct ListLibraries {
+    pub user: model::User,
+}
+
+impl Message for ListLibraries {
+    type Result = Result<Vec<model::Library>, DbError>;
+}
+
+impl Handler<ListLibraries> for DbExecutor {
+    type Result = Result<Vec<model::Library>, DbError>;
+
+    fn handle(&mut self, msg: ListLibraries, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let mut query = libraries.order(libraries_name.asc()).into_boxed();
+        if msg.user.role != Role::Admin {
+            let joined = library_members
+                .filter(library_members_users_id.eq(msg.user.id))
+                .select(library_members_libraries_id);
+            query = query.filter(libraries_id.eq_any(joined));
+        }
+        let items = query.load::<model::Library>(conn)?;
+
+        Ok(items)
+    }
+}
+
+/// Check that a user may use a library. Members can, and so can admins, who
+/// look after every library on the server. Anyone else is told it does not
+/// exist rather than that it is off limits.
+#[derive(Debug, Clone)]
+pub struct OpenLibrary {
+    /// `None` for the routes from before there were libraries, which use the
+    /// user's only library, or `DEFAULT_LIBRARY` when they have several
+    pub library: Option<String>,
+    pub user: model::User,
+}
+
+impl Message for OpenLibrary {
+    type Result = Result<model::Library, DbError>;
+}
+
+impl Handler<OpenLibrary> for DbExecutor {
+    type Result = Result<model::Library, DbError>;
+
+    fn handle(&mut self, msg: OpenLibrary, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let id = match msg.library {
+            Some(id) => id,
+            None => own_library(conn, &msg.user)?,
+        };
+        let library = find_library(conn, &id)?;
+        if msg.user.role == Role::Admin {
+            return Ok(library);
+        }
+        library_members
+            .filter(library_members_libraries_id.eq(&library.id))
+            .filter(library_members_users_id.eq(&msg.user.id))
+            .select(library_members_users_id)
+            .first::<String>(conn)
+            .optional()?
+            .map(|_| library)
+            .ok_or_else(|| not_found(&id))
+    }
+}
+
+fn own_library(conn: &SqliteConnection, user: &model::User) -> Result<String, DbError> {
+    let mut memberships = library_members
+        .filter(library_members_users_id.eq(&user.id))
+        .select(library_members_libraries_id)
+        .limit(2)
+        .load::<String>(conn)?;
+    if memberships.len() == 1 {
+        Ok(memberships.remove(0))
+    } else {
+        Ok(DEFAULT_LIBRARY.to_string())
+    }
+}
+
+/*
+ * Create a library
+ */
+#[derive(Debug, Clone)]
+pub struct CreateLibrary {
+    pub name: String,
+    /// Made the first member
+    pub user_id: String,
+}
+
+impl Message for CreateLibrary {
+    type Result = Result<model::Library, DbError>;
+}
+
+impl Handler<CreateLibrary> for DbExecutor {
+    type Result = Result<model::Library, DbError>;
+
+    fn handle(&mut self, msg: CreateLibrary, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let library = model::Library {
+            id: Uuid::new_v4().to_hyphenated().to_string(),
+            name: msg.name,
+            created_at: now(),
+        };
+        let member = model::LibraryMember {
+            library_id: library.id.clone(),
+            user_id: msg.user_id,
+            added_at: library.created_at,
+        };
+        write_transaction(conn, || {
+            diesel::insert_into(libraries).values(&library).execute(conn)?;
+            diesel::insert_into(library_members).values(&member).execute(conn)
+        })?;
+        Ok(library)
+    }
+}
+
+/*
+ * Manage who is in a library
+ */
+#[derive(Debug, Clone)]
+pub struct ListMembers {
+    pub library: String,
+}
+
+impl Message for ListMembers {
+    type Result = Result<Vec<model::User>, DbError>;
+}
+
+impl Handler<ListMembers> for DbExecutor {
+    type Result = Result<Vec<model::User>, DbError>;
+
+    fn handle(&mut self, msg: ListMembers, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        find_library(conn, &msg.library)?;
+        let members = library_members
+            .inner_join(users::table)
+            .filter(library_members_libraries_id.eq(&msg.library))
+            .order(users::users_username.asc())
+            .select(users::all_columns)
+            .load::<model::User>(conn)?;
+
+        Ok(members)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AddMember {
+    pub library: String,
+    pub user_id: String,
+}
+
+impl Message for AddMember {
+    type Result = Result<(), DbError>;
+}
+
+impl Handler<AddMember> for DbExecutor {
+    type Result = Result<(), DbError>;
+
+    fn handle(&mut self, msg: AddMember, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        write_transaction(conn, || {
+            find_library(conn, &msg.library)?;
+            users::table
+                .filter(users::users_id.eq(&msg.user_id))
+                .select(users::users_id)
+                .first::<String>(conn)
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("No user with id {}", msg.user_id)))?;
+            // Adding someone who is already a member changes nothing
+            diesel::insert_or_ignore_into(library_members)
+                .values(&model::LibraryMember {
+                    library_id: msg.library.clone(),
+                    user_id: msg.user_id.clone(),
+                    added_at: now(),
+                })
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoveMember {
+    pub library: String,
+    pub user_id: String,
+}
+
+impl Message for RemoveMember {
+    type Result = Result<(), DbError>;
+}
+
+impl Handler<RemoveMember> for DbExecutor {
+    type Result = Result<(), DbError>;
+
+    fn handle(&mut self, msg: RemoveMember, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let removed = write_transaction(conn, || {
+            diesel::delete(
+                library_members
+                    .filter(library_members_libraries_id.eq(&msg.library))
+                    .filter(library_members_users_id.eq(&msg.user_id)),
+            )
+            .execute(conn)
+        })?;
+        if removed == 0 {
+            return Err(DbError::NotFound(format!(
+                "User {} is not a member of library {}",
+                msg.user_id, msg.library
+            )));
+        }
+        Ok(())
+    }
+}
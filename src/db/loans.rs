@@ -0,0 +1,233 @@
+use ::actix::prelude::*;
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::schema::loans::dsl::*;
+use super::schema::movies::dsl::*;
+use super::types::AuditAction;
+use super::{audit, model, now, page_size, write_transaction, DbError, DbExecutor};
+
+/// The open loan of a movie in a library, if it is lent out
+fn open_loan(
+    conn: &SqliteConnection,
+    library: &str,
+    movie: &str,
+) -> QueryResult<Option<model::Loan>> {
+    loans
+        .inner_join(movies)
+        .filter(loans_movies_id.eq(movie))
+        .filter(movies_libraries_id.eq(library))
+        .filter(loans_returned_at.is_null())
+        .select(super::schema::loans::all_columns)
+        .first::<model::Loan>(conn)
+        .optional()
+}
+
+/// Mark a movie as lent out or back as a new version of it, so that its
+/// ETag changes along with `on_loan`
+fn set_on_loan(
+    conn: &SqliteConnection,
+    movie: &model::Movie,
+    lent: bool,
+    action: AuditAction,
+    actor: &str,
+) -> Result<(), DbError> {
+    diesel::update(movies.filter(movies_id.eq(&movie.id)))
+        .set((
+            movies_on_loan.eq(lent),
+            movies_version.eq(movies_version + 1),
+            movies_updated_at.eq(now()),
+        ))
+        .execute(conn)?;
+    let updated = movies.filter(movies_id.eq(&movie.id)).first(conn)?;
+    audit::record(conn, action, actor, &updated, Some(movie), Some(&updated))?;
+    Ok(())
+}
+
+/*
+ * Lend a movie out
+ */
+#[derive(Debug, Clone)]
+pub struct CheckOut {
+    pub library: String,
+    pub movie_id: String,
+    pub borrower: String,
+    pub contact: String,
+    pub due_date: NaiveDate,
+    pub actor: String,
+}
+
+impl Message for CheckOut {
+    type Result = Result<model::Loan, DbError>;
+}
+
+impl Handler<CheckOut> for DbExecutor {
+    type Result = Result<model::Loan, DbError>;
+
+    fn handle(&mut self, msg: CheckOut, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        write_transaction(conn, || {
+            let movie = movies
+                .filter(movies_id.eq(&msg.movie_id))
+                .filter(movies_libraries_id.eq(&msg.library))
+                .filter(movies_deleted_at.is_null())
+                .first::<model::Movie>(conn)
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("No movie with id {}", msg.movie_id)))?;
+            if let Some(loan) = open_loan(conn, &msg.library, &msg.movie_id)? {
+                return Err(DbError::Conflict(format!(
+                    "Movie {} is already lent to {}",
+                    msg.movie_id, loan.borrower
+                )));
+            }
+
+            let loan = model::Loan {
+                id: Uuid::new_v4().to_hyphenated().to_string(),
+                movie_id: msg.movie_id.clone(),
+                borrower: msg.borrower.clone(),
+                contact: msg.contact.clone(),
+                lent_at: now(),
+                due_date: msg.due_date,
+                returned_at: None,
+            };
+            diesel::insert_into(loans).values(&loan).execute(conn)?;
+            set_on_loan(conn, &movie, true, AuditAction::CheckOut, &msg.actor)?;
+            Ok(loan)
+        })
+    }
+}
+
+/*
+ * Take a movie back
+ */
+#[derive(Debug, Clone)]
+pub struct CheckIn {
+    pub library: String,
+    pub movie_id: String,
+    pub actor: String,
+}
+
+impl Message for CheckIn {
+    type Result = Result<model::Loan, DbError>;
+}
+
+impl Handler<CheckIn> for DbExecutor {
+    type Result = Result<model::Loan, DbError>;
+
+    fn handle(&mut self, msg: CheckIn, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        write_transaction(conn, || {
+            // A lent disc that was since put in the trash can still come back
+            let loan = open_loan(conn, &msg.library, &msg.movie_id)?.ok_or_else(|| {
+                DbError::NotFound(format!("Movie {} is not on loan", msg.movie_id))
+            })?;
+            let returned = model::Loan {
+                returned_at: Some(now()),
+                ..loan
+            };
+            diesel::update(loans.filter(loans_id.eq(&returned.id)))
+                .set(loans_returned_at.eq(returned.returned_at))
+                .execute(conn)?;
+            let movie = movies.filter(movies_id.eq(&msg.movie_id)).first(conn)?;
+            set_on_loan(conn, &movie, false, AuditAction::CheckIn, &msg.actor)?;
+            Ok(returned)
+        })
+    }
+}
+
+/*
+ * Every loan of one movie, newest first
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetMovieLoans {
+    #[serde(skip)]
+    pub library: String,
+    pub id: String,
+}
+
+impl Message for GetMovieLoans {
+    type Result = Result<Vec<model::Loan>, DbError>;
+}
+
+impl Handler<GetMovieLoans> for DbExecutor {
+    type Result = Result<Vec<model::Loan>, DbError>;
+
+    fn handle(&mut self, msg: GetMovieLoans, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let items = loans
+            .inner_join(movies)
+            .filter(loans_movies_id.eq(&msg.id))
+            .filter(movies_libraries_id.eq(&msg.library))
+            .order(loans_lent_at.desc())
+            .select(super::schema::loans::all_columns)
+            .load::<model::Loan>(conn)?;
+
+        Ok(items)
+    }
+}
+
+/*
+ * What is lent out of a library
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GetLoans {
+    #[serde(skip)]
+    pub library: String,
+    /// Only loans past their due date
+    pub overdue: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// An open loan with the movie that is out
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenLoan {
+    #[serde(flatten)]
+    pub loan: model::Loan,
+    pub movie: model::Movie,
+}
+
+impl Message for GetLoans {
+    type Result = Result<Vec<OpenLoan>, DbError>;
+}
+
+impl Handler<GetLoans> for DbExecutor {
+    type Result = Result<Vec<OpenLoan>, DbError>;
+
+    fn handle(&mut self, msg: GetLoans, _: &mut Self::Context) -> Self::Result {
+        let limit = page_size(msg.limit)?;
+        let offset = msg.offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(DbError::validation("offset must not be negative"));
+        }
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let mut query = loans
+            .inner_join(movies)
+            .filter(movies_libraries_id.eq(&msg.library))
+            .filter(loans_returned_at.is_null())
+            .order((loans_due_date.asc(), loans_lent_at.asc()))
+            .limit(limit)
+            .offset(offset)
+            .into_boxed();
+        if msg.overdue {
+            // Due dates are calendar days, so a loan is overdue once the UTC
+            // day it was due on has passed
+            query = query.filter(loans_due_date.lt(now().date()));
+        }
+        let items = query
+            .load::<(model::Loan, model::Movie)>(conn)?
+            .into_iter()
+            .map(|(loan, movie)| OpenLoan { loan, movie })
+            .collect();
+
+        Ok(items)
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use log::*;
+
+use super::schema::schema_version::dsl::*;
+
+include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
+const SCHEMA_VERSION_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS schema_version (
+  schema_version_version INTEGER PRIMARY KEY NOT NULL,
+  schema_version_name VARCHAR NOT NULL,
+  schema_version_applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);";
+
+/// A numbered pair of up/down scripts from the `migrations` directory.
+///
+/// Files are named `<version>_<name>.up.sql` and `<version>_<name>.down.sql`
+/// and are embedded into the binary at build time.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: i32,
+    pub name: String,
+    pub up: String,
+    pub down: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// A single migration to run in a given direction
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub migration: Migration,
+    pub direction: Direction,
+}
+
+impl Step {
+    pub fn sql(&self) -> &str {
+        match self.direction {
+            Direction::Up => &self.migration.up,
+            Direction::Down => &self.migration.down,
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Up => "up",
+            Direction::Down => "down",
+        };
+        write!(f, "{:04}_{} ({})", self.migration.version, self.migration.name, direction)
+    }
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Database(diesel::result::Error),
+    /// The database has been migrated past anything this build knows about
+    UnknownVersion(i32),
+    /// A target version was requested that no embedded migration has
+    UnknownTarget(i32),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MigrationError::Database(e) => write!(f, "{}", e),
+            MigrationError::UnknownVersion(v) => write!(
+                f,
+                "Database schema version {} is newer than this build supports ({})",
+                v,
+                latest_version()
+            ),
+            MigrationError::UnknownTarget(v) => write!(f, "No migration with version {}", v),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<diesel::result::Error> for MigrationError {
+    fn from(e: diesel::result::Error) -> Self {
+        MigrationError::Database(e)
+    }
+}
+
+/// All embedded migrations ordered by version
+pub fn all() -> Vec<Migration> {
+    let mut scripts: BTreeMap<i32, (String, Option<String>, Option<String>)> = BTreeMap::new();
+    for file_name in MIGRATIONS.file_names() {
+        let base = Path::new(file_name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_else(|| panic!("Invalid migration file name: {}", file_name));
+        let (stem, is_up) = if base.ends_with(".up.sql") {
+            (base.trim_end_matches(".up.sql"), true)
+        } else if base.ends_with(".down.sql") {
+            (base.trim_end_matches(".down.sql"), false)
+        } else {
+            continue;
+        };
+        let mut parts = stem.splitn(2, '_');
+        let number = parts
+            .next()
+            .and_then(|v| v.parse::<i32>().ok())
+            .unwrap_or_else(|| panic!("Migration has no version number: {}", file_name));
+        let name = parts.next().unwrap_or("").to_string();
+
+        let mut file = MIGRATIONS
+            .read(file_name)
+            .unwrap_or_else(|_| panic!("Unable to load migration file: {}", file_name));
+        let mut query = String::new();
+        file.read_to_string(&mut query)
+            .unwrap_or_else(|_| panic!("Unable to load migration query: {}", file_name));
+
+        let entry = scripts.entry(number).or_insert((name, None, None));
+        if is_up {
+            entry.1 = Some(query);
+        } else {
+            entry.2 = Some(query);
+        }
+    }
+
+    scripts
+        .into_iter()
+        .map(|(number, (name, up, down))| Migration {
+            version: number,
+            up: up.unwrap_or_else(|| panic!("Migration {:04}_{} has no up script", number, name)),
+            down: down
+                .unwrap_or_else(|| panic!("Migration {:04}_{} has no down script", number, name)),
+            name,
+        })
+        .collect()
+}
+
+/// Version of the newest embedded migration
+pub fn latest_version() -> i32 {
+    all().last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Version the database is currently at, `0` for a fresh database
+pub fn current_version(conn: &SqliteConnection) -> QueryResult<i32> {
+    let tracked = diesel::select(sql::<Bool>(
+        "EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+    ))
+    .get_result::<bool>(conn)?;
+    if !tracked {
+        return Ok(0);
+    }
+    let versions = schema_version
+        .select(schema_version_version)
+        .order(schema_version_version.desc())
+        .limit(1)
+        .load::<i32>(conn)?;
+    Ok(versions.into_iter().next().unwrap_or(0))
+}
+
+/// Steps needed to bring the database to `target`, or to the latest version
+/// when no target is given
+pub fn plan(conn: &SqliteConnection, target: Option<i32>) -> Result<Vec<Step>, MigrationError> {
+    let current = current_version(conn)?;
+    let migrations = all();
+    let latest = migrations.last().map(|m| m.version).unwrap_or(0);
+    if current > latest {
+        return Err(MigrationError::UnknownVersion(current));
+    }
+    let target = target.unwrap_or(latest);
+    if target > latest || target < 0 {
+        return Err(MigrationError::UnknownTarget(target));
+    }
+
+    let steps = if target >= current {
+        migrations
+            .into_iter()
+            .filter(|m| m.version > current && m.version <= target)
+            .map(|migration| Step {
+                migration,
+                direction: Direction::Up,
+            })
+            .collect()
+    } else {
+        migrations
+            .into_iter()
+            .rev()
+            .filter(|m| m.version <= current && m.version > target)
+            .map(|migration| Step {
+                migration,
+                direction: Direction::Down,
+            })
+            .collect()
+    };
+    Ok(steps)
+}
+
+/// Run each step in its own transaction, recording it in `schema_version`
+pub fn apply(conn: &SqliteConnection, steps: &[Step]) -> Result<(), MigrationError> {
+    conn.batch_execute(SCHEMA_VERSION_TABLE)?;
+    for step in steps {
+        info!("Running migration {}", step);
+        debug!("Migration SQL: \n{}", step.sql());
+        conn.transaction::<_, MigrationError, _>(|| {
+            conn.batch_execute(step.sql())?;
+            match step.direction {
+                Direction::Up => {
+                    diesel::insert_into(schema_version)
+                        .values((
+                            schema_version_version.eq(step.migration.version),
+                            schema_version_name.eq(&step.migration.name),
+                        ))
+                        .execute(conn)?;
+                }
+                Direction::Down => {
+                    diesel::delete(
+                        schema_version.filter(schema_version_version.eq(step.migration.version)),
+                    )
+                    .execute(conn)?;
+                }
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Bring the database up to the latest schema
+pub fn run_pending(conn: &SqliteConnection) -> Result<Vec<Step>, MigrationError> {
+    let steps = plan(conn, None)?;
+    apply(conn, &steps)?;
+    Ok(steps)
+}
@@ -1,115 +1,309 @@
+pub mod audit;
+pub mod backup;
+pub mod error;
+pub mod export;
+pub mod import;
+pub mod libraries;
+pub mod loans;
+pub mod migrations;
 pub mod model;
+pub mod people;
+pub mod pool;
+pub mod revisions;
 pub mod schema;
+pub mod tokens;
+pub mod trash;
+pub mod types;
+pub mod users;
 
 use ::actix::prelude::*;
-use actix_web::*;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use diesel::connection::TransactionManager;
+use diesel::dsl;
+use diesel::expression::{AsExpression, BoxableExpression};
 use diesel::prelude::*;
+use diesel::query_builder::QueryFragment;
+use diesel::sql_types::Bool;
+use diesel::sqlite::Sqlite;
+use diesel::AppearsOnTable;
 use diesel::r2d2::{ConnectionManager, Pool};
 use log::*;
+use serde::de::{self, Deserialize, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 use uuid::Uuid;
 
-include!(concat!(env!("OUT_DIR"), "/db_setup.rs"));
+pub use self::error::DbError;
+pub use self::types::{Aspect, AuditAction, Format, Rating, Role};
 
 pub fn init_db(db_url: &str) {
     debug!("DB URL: {}", db_url);
-    let conn = SqliteConnection::establish(&db_url)
+    let conn = SqliteConnection::establish(db_url)
         .unwrap_or_else(|_| panic!("Error connecting to {}", db_url));
-    for file_name in DB_SETUP.file_names() {
-        let mut file = DB_SETUP
-            .read(file_name)
-            .unwrap_or_else(|_| panic!("Unable to load db init file: {}", file_name));
-        let mut query = String::new();
-        file.read_to_string(&mut query)
-            .unwrap_or_else(|_| panic!("Unable to load db init query: {}", file_name));
-        debug!("Initializing table: \n{}", query);
-        ::diesel::sql_query(query)
-            .execute(&conn)
-            .expect("Fail to init db");
-    }
+    migrations::run_pending(&conn).unwrap_or_else(|e| panic!("Fail to init db: {}", e));
     info!("Database initialized");
 }
 
 pub struct DbExecutor(pub Pool<ConnectionManager<SqliteConnection>>);
 
+/// Run `f` in a transaction that takes the write lock up front.
+///
+/// A deferred transaction that reads before it writes fails straight away if
+/// another connection wrote in between, without waiting out the busy
+/// timeout. Nested calls use a savepoint inside the outer transaction.
+pub fn write_transaction<T, E, F>(conn: &SqliteConnection, f: F) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E>,
+    E: From<diesel::result::Error>,
+{
+    let depth =
+        TransactionManager::<SqliteConnection>::get_transaction_depth(conn.transaction_manager());
+    if depth == 0 {
+        conn.immediate_transaction(f)
+    } else {
+        conn.transaction(f)
+    }
+}
+
 impl Actor for DbExecutor {
     type Context = SyncContext<Self>;
 }
 
+/// The movie versions a write may apply to, taken from `If-Match`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Precondition {
+    /// `If-Match: *`, any existing version
+    Any,
+    Versions(Vec<i32>),
+}
+
+impl Precondition {
+    pub fn matches(&self, version: i32) -> bool {
+        match self {
+            Precondition::Any => true,
+            Precondition::Versions(versions) => versions.contains(&version),
+        }
+    }
+}
+
+/// Load a movie in a library for writing, failing if it is missing or if its
+/// version does not satisfy the precondition
+fn movie_for_write(
+    conn: &SqliteConnection,
+    library: &str,
+    id: &str,
+    precondition: &Precondition,
+) -> Result<model::Movie, DbError> {
+    use self::schema::movies::dsl::*;
+
+    let movie = movies
+        .filter(movies_id.eq(id))
+        .filter(movies_libraries_id.eq(library))
+        .filter(movies_deleted_at.is_null())
+        .first::<model::Movie>(conn)
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("No movie with id {}", id)))?;
+    if !precondition.matches(movie.version) {
+        return Err(stale(&movie));
+    }
+    Ok(movie)
+}
+
+/// Current time as stored in timestamp columns
+pub fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+/// Whether two movies differ only in their bookkeeping columns and loan state
+fn same_content(a: &model::Movie, b: &model::Movie) -> bool {
+    *a == model::Movie {
+        version: a.version,
+        created_at: a.created_at,
+        updated_at: a.updated_at,
+        on_loan: a.on_loan,
+        ..b.clone()
+    }
+}
+
+fn stale(movie: &model::Movie) -> DbError {
+    DbError::PreconditionFailed(format!(
+        "Movie {} has been modified, the current version is {}",
+        movie.id, movie.version
+    ))
+}
+
 /*
  * Create a new movie
  */
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateMovie {
+    pub library: String,
+    /// Client supplied id, generated when absent
+    pub id: Option<String>,
     pub title: String,
-    pub rating: String,
+    pub rating: Rating,
     pub category: String,
-    pub format: String,
-    pub aspect: String,
+    pub format: Format,
+    pub aspect: Aspect,
     pub actors: String,
     pub drawer: String,
     pub column: String,
+    /// Who is making the change, for the audit log
+    pub actor: String,
+}
+
+/// The stored movie, and whether this request inserted it or found an
+/// identical movie already saved under the client supplied id
+#[derive(Debug, Clone)]
+pub struct Created {
+    pub movie: model::Movie,
+    pub created: bool,
 }
 
 impl Message for CreateMovie {
-    type Result = Result<(), Error>;
+    type Result = Result<Created, DbError>;
 }
 
 impl Handler<CreateMovie> for DbExecutor {
-    type Result = Result<(), Error>;
+    type Result = Result<Created, DbError>;
 
     fn handle(&mut self, msg: CreateMovie, _: &mut Self::Context) -> Self::Result {
-        use self::schema::movies::dsl::*;
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        create_movie(conn, &msg)
+    }
+}
+
+/// Insert a movie, or find the identical one already saved under its id
+pub fn create_movie(conn: &SqliteConnection, msg: &CreateMovie) -> Result<Created, DbError> {
+    use self::schema::movies::dsl::*;
+
+    write_transaction(conn, || {
+        let cast = people::resolve_actors(conn, &msg.actors)?;
 
-        let uuid = Uuid::new_v4().to_string();
+        let created = now();
+        let new_id = msg.id.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
         let new_movie = model::Movie {
-            id: uuid,
+            id: new_id.clone(),
             title: msg.title.clone(),
-            rating: msg.rating.clone(),
+            rating: msg.rating,
             category: msg.category.clone(),
-            format: msg.format.clone(),
-            aspect: msg.aspect.clone(),
-            actors: msg.actors.clone(),
+            format: msg.format,
+            aspect: msg.aspect,
+            actors: people::display_names(&cast),
             drawer: msg.drawer.clone(),
             column: msg.column.clone(),
+            version: revisions::next_version(conn, &new_id)?,
+            created_at: created,
+            updated_at: created,
+            deleted_at: None,
+            library_id: msg.library.clone(),
+            on_loan: false,
         };
 
-        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let existing = movies
+            .filter(movies_id.eq(&new_movie.id))
+            .first::<model::Movie>(conn)
+            .optional()?;
+        match existing {
+            // Ids are unique across libraries, but say no more than that
+            Some(ref movie) if movie.library_id != msg.library => {
+                return Err(DbError::Conflict(format!(
+                    "The id {} is already in use",
+                    movie.id
+                )))
+            }
+            Some(ref movie) if movie.deleted_at.is_some() => {
+                return Err(DbError::Conflict(format!(
+                    "Movie {} is in the trash, restore it instead",
+                    movie.id
+                )))
+            }
+            Some(movie) if same_content(&movie, &new_movie) => {
+                return Ok(Created {
+                    movie,
+                    created: false,
+                })
+            }
+            Some(_) => {
+                return Err(DbError::Conflict(format!(
+                    "A different movie with id {} already exists",
+                    new_movie.id
+                )))
+            }
+            None => {}
+        }
 
         diesel::insert_into(movies)
             .values(&new_movie)
-            .execute(conn)
-            .map_err(|_| error::ErrorInternalServerError("Error inserting movie"))?;
+            .execute(conn)?;
+        people::replace_actors(conn, &new_movie.id, &cast)?;
+        audit::record(
+            conn,
+            AuditAction::Create,
+            &msg.actor,
+            &new_movie,
+            None,
+            Some(&new_movie),
+        )?;
 
-        Ok(())
-    }
+        Ok(Created {
+            movie: new_movie,
+            created: true,
+        })
+    })
 }
 
 /*
- * Delete movie
+ * Move a movie to the trash
  */
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeleteMovie {
+    pub library: String,
     pub id: String,
+    pub precondition: Precondition,
+    pub actor: String,
 }
 
 impl Message for DeleteMovie {
-    type Result = Result<(), Error>;
+    type Result = Result<(), DbError>;
 }
 
 impl Handler<DeleteMovie> for DbExecutor {
-    type Result = Result<(), Error>;
+    type Result = Result<(), DbError>;
 
     fn handle(&mut self, msg: DeleteMovie, _: &mut Self::Context) -> Self::Result {
         use self::schema::movies::dsl::*;
 
-        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let conn: &SqliteConnection = &*self.0.get()?;
 
-        diesel::delete(movies.filter(movies_id.eq(msg.id)))
-            .execute(conn)
-            .map_err(|_| error::ErrorInternalServerError("Error deleting movie"))?;
+        write_transaction(conn, || {
+            let movie = movie_for_write(conn, &msg.library, &msg.id, &msg.precondition)?;
+            let deleted = diesel::update(
+                movies
+                    .filter(movies_id.eq(&msg.id))
+                    .filter(movies_version.eq(movie.version)),
+            )
+            .set((
+                movies_deleted_at.eq(Some(now())),
+                movies_version.eq(movies_version + 1),
+                movies_updated_at.eq(now()),
+            ))
+            .execute(conn)?;
+            if deleted == 0 {
+                return Err(stale(&movie));
+            }
+            let trashed = movies.filter(movies_id.eq(&msg.id)).first(conn)?;
+            audit::record(
+                conn,
+                AuditAction::Delete,
+                &msg.actor,
+                &movie,
+                Some(&movie),
+                Some(&trashed),
+            )?;
 
-        Ok(())
+            Ok(())
+        })
     }
 }
 
@@ -118,30 +312,31 @@ impl Handler<DeleteMovie> for DbExecutor {
  */
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetMovie {
+    /// Taken from the path rather than the query string
+    #[serde(skip)]
+    pub library: String,
     pub id: String,
 }
 
 impl Message for GetMovie {
-    type Result = Result<model::Movie, Error>;
+    type Result = Result<model::Movie, DbError>;
 }
 
 impl Handler<GetMovie> for DbExecutor {
-    type Result = Result<model::Movie, Error>;
+    type Result = Result<model::Movie, DbError>;
 
     fn handle(&mut self, msg: GetMovie, _: &mut Self::Context) -> Self::Result {
         use self::schema::movies::dsl::*;
 
-        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let conn: &SqliteConnection = &*self.0.get()?;
 
-        let mut items = movies
-            .filter(movies_id.eq(msg.id))
-            .limit(1)
-            .load::<model::Movie>(conn)
-            .map_err(|_| error::ErrorInternalServerError("Error getting movie"))?;
-
-        items
-            .pop()
-            .ok_or(error::ErrorInternalServerError("No movie with that id"))
+        movies
+            .filter(movies_id.eq(&msg.id))
+            .filter(movies_libraries_id.eq(&msg.library))
+            .filter(movies_deleted_at.is_null())
+            .first::<model::Movie>(conn)
+            .optional()?
+            .ok_or_else(|| DbError::NotFound(format!("No movie with id {}", msg.id)))
     }
 }
 
@@ -150,70 +345,791 @@ impl Handler<GetMovie> for DbExecutor {
  */
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateMovie {
+    pub library: String,
     pub id: String,
     pub title: String,
-    pub rating: String,
+    pub rating: Rating,
     pub category: String,
-    pub format: String,
-    pub aspect: String,
+    pub format: Format,
+    pub aspect: Aspect,
     pub actors: String,
     pub drawer: String,
     pub column: String,
+    /// Why `column` is not allowed, unless the movie is already in it
+    pub column_error: Option<String>,
+    pub precondition: Precondition,
+    pub actor: String,
 }
 
 impl Message for UpdateMovie {
-    type Result = Result<(), Error>;
+    type Result = Result<model::Movie, DbError>;
 }
 
 impl Handler<UpdateMovie> for DbExecutor {
-    type Result = Result<(), Error>;
+    type Result = Result<model::Movie, DbError>;
 
     fn handle(&mut self, msg: UpdateMovie, _: &mut Self::Context) -> Self::Result {
         use self::schema::movies::dsl::*;
 
-        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let conn: &SqliteConnection = &*self.0.get()?;
 
-        let target = movies.filter(movies_id.eq(msg.id));
-        diesel::update(target)
-            .set((
-                movies_title.eq(msg.title),
-                movies_rating.eq(msg.rating),
-                movies_category.eq(msg.category),
-                movies_format.eq(msg.format),
-                movies_aspect.eq(msg.aspect),
-                movies_actors.eq(msg.actors),
-                movies_drawer.eq(msg.drawer),
-                movies_column.eq(msg.column),
-            ))
-            .execute(conn)
-            .map_err(|_| error::ErrorInternalServerError("Error inserting movie"))?;
+        write_transaction(conn, || {
+            let movie = movie_for_write(conn, &msg.library, &msg.id, &msg.precondition)?;
+            check_kept_column(&movie, &msg.column, &msg.column_error)?;
+            let cast = people::resolve_actors(conn, &msg.actors)?;
+
+            let target = movies
+                .filter(movies_id.eq(&msg.id))
+                .filter(movies_version.eq(movie.version));
+            let updated = diesel::update(target)
+                .set((
+                    movies_title.eq(&msg.title),
+                    movies_rating.eq(&msg.rating),
+                    movies_category.eq(&msg.category),
+                    movies_format.eq(&msg.format),
+                    movies_aspect.eq(&msg.aspect),
+                    movies_actors.eq(people::display_names(&cast)),
+                    movies_drawer.eq(&msg.drawer),
+                    movies_column.eq(&msg.column),
+                    movies_version.eq(movies_version + 1),
+                    movies_updated_at.eq(now()),
+                ))
+                .execute(conn)?;
+            if updated == 0 {
+                return Err(stale(&movie));
+            }
+            people::replace_actors(conn, &msg.id, &cast)?;
+
+            let updated = movies.filter(movies_id.eq(&msg.id)).first(conn)?;
+            audit::record(
+                conn,
+                AuditAction::Update,
+                &msg.actor,
+                &movie,
+                Some(&movie),
+                Some(&updated),
+            )?;
+            Ok(updated)
+        })
+    }
+}
 
-        Ok(())
+/// Let a movie stay in a column that is no longer configured, but not move
+/// into one
+fn check_kept_column(
+    movie: &model::Movie,
+    column: &str,
+    error: &Option<String>,
+) -> Result<(), DbError> {
+    match error {
+        Some(message) if movie.column != column => Err(DbError::field("column", message.clone())),
+        _ => Ok(()),
     }
 }
 
 /*
- * Get all movies
+ * Update some fields of a movie
  */
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GetAllMovies;
+pub struct PatchMovie {
+    pub library: String,
+    pub id: String,
+    pub changes: model::MovieChanges,
+    /// Why the new column is not allowed, unless the movie is already in it
+    pub column_error: Option<String>,
+    pub precondition: Precondition,
+    pub actor: String,
+}
+
+impl Message for PatchMovie {
+    type Result = Result<model::Movie, DbError>;
+}
+
+impl Handler<PatchMovie> for DbExecutor {
+    type Result = Result<model::Movie, DbError>;
+
+    fn handle(&mut self, msg: PatchMovie, _: &mut Self::Context) -> Self::Result {
+        use self::schema::movies::dsl::*;
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        write_transaction(conn, || {
+            let movie = movie_for_write(conn, &msg.library, &msg.id, &msg.precondition)?;
+            if let Some(ref column) = msg.changes.column {
+                check_kept_column(&movie, column, &msg.column_error)?;
+            }
+            let mut changes = msg.changes.clone();
+            let cast = match changes.actors {
+                Some(ref names) => Some(people::resolve_actors(conn, names)?),
+                None => None,
+            };
+            if let Some(ref cast) = cast {
+                changes.actors = Some(people::display_names(cast));
+            }
+
+            // An empty patch leaves the movie, and so its version, untouched
+            if changes == model::MovieChanges::default() {
+                return Ok(movie);
+            }
+            let target = movies
+                .filter(movies_id.eq(&msg.id))
+                .filter(movies_version.eq(movie.version));
+            let updated = diesel::update(target)
+                .set((
+                    &changes,
+                    movies_version.eq(movies_version + 1),
+                    movies_updated_at.eq(now()),
+                ))
+                .execute(conn)?;
+            if updated == 0 {
+                return Err(stale(&movie));
+            }
+            if let Some(ref cast) = cast {
+                people::replace_actors(conn, &msg.id, cast)?;
+            }
+
+            let updated = movies.filter(movies_id.eq(&msg.id)).first(conn)?;
+            audit::record(
+                conn,
+                AuditAction::Update,
+                &msg.actor,
+                &movie,
+                Some(&movie),
+                Some(&updated),
+            )?;
+            Ok(updated)
+        })
+    }
+}
+
+/*
+ * Get all movies
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MovieFilter {
+    /// Case-insensitive substring of the title
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rating: Option<Rating>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aspect: Option<Aspect>,
+    /// Case-insensitive substring of the actors list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drawer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+    /// Added at or after this date or time
+    #[serde(
+        default,
+        deserialize_with = "deserialize_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created_after: Option<NaiveDateTime>,
+    /// Added before this date or time
+    #[serde(
+        default,
+        deserialize_with = "deserialize_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created_before: Option<NaiveDateTime>,
+    /// Last changed at or after this date or time
+    #[serde(
+        default,
+        deserialize_with = "deserialize_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_after: Option<NaiveDateTime>,
+    /// Last changed before this date or time
+    #[serde(
+        default,
+        deserialize_with = "deserialize_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_before: Option<NaiveDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortBy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<Order>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Title,
+    Rating,
+    Category,
+    Format,
+    Aspect,
+    Drawer,
+    Column,
+    #[serde(rename = "created_at")]
+    CreatedAt,
+    #[serde(rename = "updated_at")]
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+type MovieQuery = schema::movies::BoxedQuery<'static, Sqlite>;
+
+/// Parse a UTC date (`2018-12-01`, meaning midnight) or date and time
+/// (`2018-12-01T18:30:00`) from a query string
+fn deserialize_time<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    let value = value.trim();
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| date.and_hms(0, 0, 0))
+        })
+        .map(Some)
+        .map_err(|_| {
+            de::Error::custom(format!(
+                "invalid time `{}`, expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS",
+                value
+            ))
+        })
+}
+
+/// Wrap a user supplied string in `%` for a LIKE match, escaping wildcards
+fn contains_pattern(needle: &str) -> String {
+    let escaped = needle
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn sort_by<C>(query: MovieQuery, column: C, order: Order) -> MovieQuery
+where
+    C: ExpressionMethods + QueryFragment<Sqlite> + AppearsOnTable<schema::movies::table> + 'static,
+{
+    match order {
+        Order::Asc => query.order_by(column.asc()),
+        Order::Desc => query.order_by(column.desc()),
+    }
+}
+
+type MoviePredicate = Box<dyn BoxableExpression<schema::movies::table, Sqlite, SqlType = Bool>>;
+
+/// Rows past `key` in `column` when sorted in `order`, and rows level with it
+fn past_key<C, T>(column: C, key: T, order: Order) -> (MoviePredicate, MoviePredicate)
+where
+    C: ExpressionMethods + Copy,
+    T: AsExpression<C::SqlType> + Clone,
+    dsl::Gt<C, T>: BoxableExpression<schema::movies::table, Sqlite, SqlType = Bool> + 'static,
+    dsl::Lt<C, T>: BoxableExpression<schema::movies::table, Sqlite, SqlType = Bool> + 'static,
+    dsl::Eq<C, T>: BoxableExpression<schema::movies::table, Sqlite, SqlType = Bool> + 'static,
+{
+    let past: MoviePredicate = match order {
+        Order::Asc => Box::new(column.gt(key.clone())),
+        Order::Desc => Box::new(column.lt(key.clone())),
+    };
+    (past, Box::new(column.eq(key)))
+}
+
+impl MovieFilter {
+    /// Movies of a library outside the trash matching every filter that was
+    /// supplied, unordered
+    fn filtered(&self, library: &str) -> MovieQuery {
+        use self::schema::movies::dsl::*;
+
+        let mut query = movies
+            .filter(movies_libraries_id.eq(library.to_string()))
+            .filter(movies_deleted_at.is_null())
+            .into_boxed();
+        if let Some(ref value) = self.title {
+            query = query.filter(movies_title.like(contains_pattern(value)).escape('\\'));
+        }
+        if let Some(ref value) = self.rating {
+            query = query.filter(movies_rating.eq(*value));
+        }
+        if let Some(ref value) = self.category {
+            query = query.filter(movies_category.eq(value.clone()));
+        }
+        if let Some(ref value) = self.format {
+            query = query.filter(movies_format.eq(*value));
+        }
+        if let Some(ref value) = self.aspect {
+            query = query.filter(movies_aspect.eq(*value));
+        }
+        if let Some(ref value) = self.actor {
+            query = query.filter(movies_actors.like(contains_pattern(value)).escape('\\'));
+        }
+        if let Some(ref value) = self.drawer {
+            query = query.filter(movies_drawer.eq(value.clone()));
+        }
+        if let Some(ref value) = self.column {
+            query = query.filter(movies_column.eq(value.clone()));
+        }
+        if let Some(value) = self.created_after {
+            query = query.filter(movies_created_at.ge(value));
+        }
+        if let Some(value) = self.created_before {
+            query = query.filter(movies_created_at.lt(value));
+        }
+        if let Some(value) = self.updated_after {
+            query = query.filter(movies_updated_at.ge(value));
+        }
+        if let Some(value) = self.updated_before {
+            query = query.filter(movies_updated_at.lt(value));
+        }
+        query
+    }
+
+    /// Filtered movies in the requested order, ties broken by title then id
+    fn sorted(&self, library: &str) -> MovieQuery {
+        use self::schema::movies::dsl::*;
+
+        let order = self.order.unwrap_or_default();
+        let query = self.filtered(library);
+        let query = match self.sort.unwrap_or_default() {
+            SortBy::Title => sort_by(query, movies_title, order),
+            SortBy::Rating => sort_by(query, movies_rating, order),
+            SortBy::Category => sort_by(query, movies_category, order),
+            SortBy::Format => sort_by(query, movies_format, order),
+            SortBy::Aspect => sort_by(query, movies_aspect, order),
+            SortBy::Drawer => sort_by(query, movies_drawer, order),
+            SortBy::Column => sort_by(query, movies_column, order),
+            SortBy::CreatedAt => sort_by(query, movies_created_at, order),
+            SortBy::UpdatedAt => sort_by(query, movies_updated_at, order),
+        };
+        query
+            .then_order_by(movies_title.asc())
+            .then_order_by(movies_id.asc())
+    }
+
+    /// Like `sorted`, but only the movies that come after `last` in that
+    /// order, so paging through them by (sort key, title, id) neither skips
+    /// nor repeats movies when others are added or removed in between
+    fn sorted_after(&self, library: &str, last: &model::Movie) -> MovieQuery {
+        use self::schema::movies::dsl::*;
+
+        let order = self.order.unwrap_or_default();
+        let (past, level) = match self.sort.unwrap_or_default() {
+            SortBy::Title => past_key(movies_title, last.title.clone(), order),
+            SortBy::Rating => past_key(movies_rating, last.rating, order),
+            SortBy::Category => past_key(movies_category, last.category.clone(), order),
+            SortBy::Format => past_key(movies_format, last.format, order),
+            SortBy::Aspect => past_key(movies_aspect, last.aspect, order),
+            SortBy::Drawer => past_key(movies_drawer, last.drawer.clone(), order),
+            SortBy::Column => past_key(movies_column, last.column.clone(), order),
+            SortBy::CreatedAt => past_key(movies_created_at, last.created_at, order),
+            SortBy::UpdatedAt => past_key(movies_updated_at, last.updated_at, order),
+        };
+        let tie = movies_title.gt(last.title.clone()).or(movies_title
+            .eq(last.title.clone())
+            .and(movies_id.gt(last.id.clone())));
+        self.sorted(library).filter(past.or(level.and(tie)))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GetAllMovies {
+    #[serde(skip)]
+    pub library: String,
+    #[serde(flatten)]
+    pub filter: MovieFilter,
+}
 
 impl Message for GetAllMovies {
-    type Result = Result<Vec<model::Movie>, Error>;
+    type Result = Result<Vec<model::Movie>, DbError>;
 }
 
 impl Handler<GetAllMovies> for DbExecutor {
-    type Result = Result<Vec<model::Movie>, Error>;
+    type Result = Result<Vec<model::Movie>, DbError>;
+
+    fn handle(&mut self, msg: GetAllMovies, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let items = msg.filter.sorted(&msg.library).load::<model::Movie>(conn)?;
+
+        Ok(items)
+    }
+}
+
+/*
+ * List movies a page at a time
+ */
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListMovies {
+    /// Left out of the `next` and `prev` links, which stay in the library
+    #[serde(skip)]
+    pub library: String,
+    #[serde(flatten)]
+    pub filter: MovieFilter,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    /// Opaque position returned in a previous page's `next` or `prev` link
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Check a requested page size, falling back to the default
+fn page_size(limit: Option<i64>) -> Result<i64, DbError> {
+    match limit {
+        Some(limit) if !(1..=MAX_PAGE_SIZE).contains(&limit) => Err(DbError::validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        ))),
+        Some(limit) => Ok(limit),
+        None => Ok(DEFAULT_PAGE_SIZE),
+    }
+}
+
+/// One page of movies along with the requests for its neighbouring pages
+#[derive(Debug, Clone)]
+pub struct MoviePage {
+    pub items: Vec<model::Movie>,
+    pub total: i64,
+    pub next: Option<ListMovies>,
+    pub prev: Option<ListMovies>,
+}
+
+/// Keyset position in a title-ordered listing
+#[derive(Debug, Clone, PartialEq)]
+struct Cursor {
+    /// Whether the page starts after or ends before this position
+    after: bool,
+    title: String,
+    id: String,
+}
+
+const CURSOR_SEPARATOR: char = '\u{1f}';
+
+impl Cursor {
+    fn after(movie: &model::Movie) -> Self {
+        Cursor {
+            after: true,
+            title: movie.title.clone(),
+            id: movie.id.clone(),
+        }
+    }
+
+    fn before(movie: &model::Movie) -> Self {
+        Cursor {
+            after: false,
+            ..Cursor::after(movie)
+        }
+    }
+
+    fn encode(&self) -> String {
+        let direction = if self.after { "a" } else { "b" };
+        let raw = [direction, &self.title, &self.id].join(&CURSOR_SEPARATOR.to_string());
+        base64::encode_config(&raw, base64::URL_SAFE_NO_PAD)
+    }
+
+    fn decode(cursor: &str) -> Result<Self, DbError> {
+        let invalid = || DbError::validation("Invalid cursor");
+        let raw = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let mut parts = raw.splitn(3, CURSOR_SEPARATOR);
+        let after = match parts.next() {
+            Some("a") => true,
+            Some("b") => false,
+            _ => return Err(invalid()),
+        };
+        match (parts.next(), parts.next()) {
+            (Some(title), Some(id)) => Ok(Cursor {
+                after,
+                title: title.to_string(),
+                id: id.to_string(),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl ListMovies {
+    fn with_cursor(&self, cursor: Cursor) -> Self {
+        ListMovies {
+            cursor: Some(cursor.encode()),
+            offset: None,
+            ..self.clone()
+        }
+    }
+
+    fn with_offset(&self, offset: i64) -> Self {
+        ListMovies {
+            cursor: None,
+            offset: Some(offset),
+            ..self.clone()
+        }
+    }
+
+    /// Page through the results by position, for any sort order
+    fn offset_page(
+        &self,
+        conn: &SqliteConnection,
+        limit: i64,
+        offset: i64,
+        total: i64,
+    ) -> Result<MoviePage, DbError> {
+        if offset < 0 {
+            return Err(DbError::validation("offset must not be negative"));
+        }
+        let items = self
+            .filter
+            .sorted(&self.library)
+            .limit(limit)
+            .offset(offset)
+            .load::<model::Movie>(conn)?;
+        let next = if offset + limit < total {
+            Some(self.with_offset(offset + limit))
+        } else {
+            None
+        };
+        let prev = if offset > 0 {
+            Some(self.with_offset((offset - limit).max(0)))
+        } else {
+            None
+        };
+        Ok(MoviePage {
+            items,
+            total,
+            next,
+            prev,
+        })
+    }
 
-    fn handle(&mut self, _: GetAllMovies, _: &mut Self::Context) -> Self::Result {
+    /// Page through title-ordered results by (title, id), which stays stable
+    /// while movies are added or removed
+    fn cursor_page(
+        &self,
+        conn: &SqliteConnection,
+        limit: i64,
+        cursor: Option<Cursor>,
+        total: i64,
+    ) -> Result<MoviePage, DbError> {
         use self::schema::movies::dsl::*;
 
-        let conn: &SqliteConnection = &self.0.get().unwrap();
+        let ascending = self.filter.order.unwrap_or_default() == Order::Asc;
+        let forward = cursor.as_ref().map(|c| c.after).unwrap_or(true);
 
-        let items = movies
-            .load::<model::Movie>(conn)
-            .map_err(|_| error::ErrorInternalServerError("Error getting all movies"))?;
+        let mut query = self.filter.filtered(&self.library);
+        if let Some(Cursor { title, id, .. }) = cursor.clone() {
+            let same_title = movies_title.eq(title.clone());
+            query = match (forward == ascending, forward) {
+                (true, true) => {
+                    query.filter(movies_title.gt(title).or(same_title.and(movies_id.gt(id))))
+                }
+                (true, false) => {
+                    query.filter(movies_title.gt(title).or(same_title.and(movies_id.lt(id))))
+                }
+                (false, true) => {
+                    query.filter(movies_title.lt(title).or(same_title.and(movies_id.gt(id))))
+                }
+                (false, false) => {
+                    query.filter(movies_title.lt(title).or(same_title.and(movies_id.lt(id))))
+                }
+            };
+        }
+        query = match (forward == ascending, forward) {
+            (true, true) => query.order_by(movies_title.asc()).then_order_by(movies_id.asc()),
+            (true, false) => query.order_by(movies_title.asc()).then_order_by(movies_id.desc()),
+            (false, true) => query.order_by(movies_title.desc()).then_order_by(movies_id.asc()),
+            (false, false) => query.order_by(movies_title.desc()).then_order_by(movies_id.desc()),
+        };
 
-        Ok(items)
+        let mut items = query.limit(limit + 1).load::<model::Movie>(conn)?;
+        let more = items.len() as i64 > limit;
+        items.truncate(limit as usize);
+        if !forward {
+            items.reverse();
+        }
+
+        let (has_next, has_prev) = if forward {
+            (more, cursor.is_some())
+        } else {
+            (true, more)
+        };
+        let next = items
+            .last()
+            .filter(|_| has_next)
+            .map(|last| self.with_cursor(Cursor::after(last)));
+        let prev = items
+            .first()
+            .filter(|_| has_prev)
+            .map(|first| self.with_cursor(Cursor::before(first)));
+        Ok(MoviePage {
+            items,
+            total,
+            next,
+            prev,
+        })
+    }
+}
+
+impl Message for ListMovies {
+    type Result = Result<MoviePage, DbError>;
+}
+
+impl Handler<ListMovies> for DbExecutor {
+    type Result = Result<MoviePage, DbError>;
+
+    fn handle(&mut self, msg: ListMovies, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let limit = page_size(msg.limit)?;
+        let total = msg.filter.filtered(&msg.library).count().get_result::<i64>(conn)?;
+        let by_title = msg.filter.sort.unwrap_or_default() == SortBy::Title;
+
+        match (&msg.cursor, msg.offset) {
+            (Some(_), Some(_)) => Err(DbError::validation(
+                "cursor and offset cannot be used together",
+            )),
+            (Some(_), None) if !by_title => Err(DbError::validation(
+                "cursor pagination is only available when sorting by title",
+            )),
+            (Some(cursor), None) => {
+                let cursor = Cursor::decode(cursor)?;
+                msg.cursor_page(conn, limit, Some(cursor), total)
+            }
+            (None, None) if by_title => msg.cursor_page(conn, limit, None, total),
+            (None, offset) => msg.offset_page(conn, limit, offset.unwrap_or(0), total),
+        }
+    }
+}
+
+/*
+ * Full-text search over titles and actors
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchMovies {
+    #[serde(skip)]
+    pub library: String,
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+const SEARCH_SQL: &str = "
+SELECT movies.*,
+       snippet(movies_fts, 1, '<mark>', '</mark>', '…', 16) AS title_snippet,
+       snippet(movies_fts, 2, '<mark>', '</mark>', '…', 16) AS actors_snippet,
+       bm25(movies_fts, 0.0, 10.0, 1.0) AS rank
+FROM movies_fts
+JOIN movies ON movies.movies_id = movies_fts.movies_id
+WHERE movies_fts MATCH ?
+  AND movies.movies_libraries_id = ?
+  AND movies.movies_deleted_at IS NULL
+ORDER BY rank
+LIMIT ?";
+
+impl SearchMovies {
+    /// Turn free text into an FTS5 query matching every word as a prefix, in
+    /// any order. Quoting each word keeps FTS5 operators in user input inert.
+    fn match_expression(&self) -> Option<String> {
+        let terms = self
+            .q
+            .split_whitespace()
+            .map(|word| word.replace('"', ""))
+            .filter(|word| !word.is_empty())
+            .map(|word| format!("\"{}\"*", word))
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            None
+        } else {
+            Some(terms.join(" "))
+        }
+    }
+}
+
+impl Message for SearchMovies {
+    type Result = Result<Vec<model::SearchHit>, DbError>;
+}
+
+impl Handler<SearchMovies> for DbExecutor {
+    type Result = Result<Vec<model::SearchHit>, DbError>;
+
+    fn handle(&mut self, msg: SearchMovies, _: &mut Self::Context) -> Self::Result {
+        use diesel::sql_types::{BigInt, Text};
+
+        if msg.q.trim().is_empty() {
+            return Err(DbError::validation("Search query must not be empty"));
+        }
+        let limit = page_size(msg.limit)?;
+        // Nothing but quotes can't match anything
+        let expression = match msg.match_expression() {
+            Some(expression) => expression,
+            None => return Ok(Vec::new()),
+        };
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let hits = diesel::sql_query(SEARCH_SQL)
+            .bind::<Text, _>(expression)
+            .bind::<Text, _>(&msg.library)
+            .bind::<BigInt, _>(limit)
+            .load::<model::SearchHit>(conn)?;
+
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expression(q: &str) -> Option<String> {
+        SearchMovies {
+            library: String::new(),
+            q: q.to_string(),
+            limit: None,
+        }
+        .match_expression()
+    }
+
+    #[test]
+    fn each_word_is_quoted_and_matched_as_a_prefix() {
+        assert_eq!(expression("alien"), Some(r#""alien"*"#.to_string()));
+        assert_eq!(
+            expression("  sigourney\tweaver "),
+            Some(r#""sigourney"* "weaver"*"#.to_string())
+        );
+    }
+
+    #[test]
+    fn operators_are_quoted_like_any_other_word() {
+        assert_eq!(
+            expression("good AND bad OR NOT NEAR(x)"),
+            Some(r#""good"* "AND"* "bad"* "OR"* "NOT"* "NEAR(x)"*"#.to_string())
+        );
+        assert_eq!(
+            expression("title:alien al*en ^heat"),
+            Some(r#""title:alien"* "al*en"* "^heat"*"#.to_string())
+        );
+    }
+
+    #[test]
+    fn quotes_are_dropped_from_words() {
+        assert_eq!(
+            expression(r#""sigourney weaver""#),
+            Some(r#""sigourney"* "weaver"*"#.to_string())
+        );
+        assert_eq!(expression(r#"de"niro"#), Some(r#""deniro"*"#.to_string()));
+        assert_eq!(expression(r#"" """#), None);
+        assert_eq!(expression(" "), None);
     }
 }
@@ -1,8 +1,22 @@
 use super::schema::*;
+use super::types::{Aspect, AuditAction, Format, Json, Rating, Role};
 
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::sql_types::{Double, Text};
 use serde_derive::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Identifiable, Insertable, AsChangeset)]
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    Queryable,
+    QueryableByName,
+    Identifiable,
+    Insertable,
+    AsChangeset,
+)]
 #[table_name = "movies"]
 #[primary_key(movies_id)]
 pub struct Movie {
@@ -11,17 +25,269 @@ pub struct Movie {
     #[column_name = "movies_title"]
     pub title: String,
     #[column_name = "movies_rating"]
-    pub rating: String,
+    pub rating: Rating,
     #[column_name = "movies_category"]
     pub category: String,
     #[column_name = "movies_format"]
-    pub format: String,
+    pub format: Format,
     #[column_name = "movies_aspect"]
-    pub aspect: String,
+    pub aspect: Aspect,
     #[column_name = "movies_actors"]
     pub actors: String,
     #[column_name = "movies_drawer"]
     pub drawer: String,
     #[column_name = "movies_column"]
     pub column: String,
+    /// Bumped on every write and served as the `ETag`
+    #[column_name = "movies_version"]
+    pub version: i32,
+    /// UTC
+    #[column_name = "movies_created_at"]
+    pub created_at: NaiveDateTime,
+    /// UTC
+    #[column_name = "movies_updated_at"]
+    pub updated_at: NaiveDateTime,
+    /// UTC, set while the movie is in the trash
+    #[column_name = "movies_deleted_at"]
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Missing from revisions saved before libraries existed
+    #[column_name = "movies_libraries_id"]
+    #[serde(default)]
+    pub library_id: String,
+    /// Set while the disc is lent out, mirroring its open `Loan`
+    #[column_name = "movies_on_loan"]
+    #[serde(default)]
+    pub on_loan: bool,
+}
+
+/// A sparse set of movie columns to overwrite; `None` fields are left alone
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, AsChangeset)]
+#[table_name = "movies"]
+pub struct MovieChanges {
+    #[column_name = "movies_title"]
+    pub title: Option<String>,
+    #[column_name = "movies_rating"]
+    pub rating: Option<Rating>,
+    #[column_name = "movies_category"]
+    pub category: Option<String>,
+    #[column_name = "movies_format"]
+    pub format: Option<Format>,
+    #[column_name = "movies_aspect"]
+    pub aspect: Option<Aspect>,
+    #[column_name = "movies_actors"]
+    pub actors: Option<String>,
+    #[column_name = "movies_drawer"]
+    pub drawer: Option<String>,
+    #[column_name = "movies_column"]
+    pub column: Option<String>,
+}
+
+/// A full-text search match with the matching parts of each field wrapped in
+/// `<mark>` tags
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+pub struct SearchHit {
+    #[diesel(embed)]
+    #[serde(flatten)]
+    pub movie: Movie,
+    #[sql_type = "Text"]
+    pub title_snippet: String,
+    #[sql_type = "Text"]
+    pub actors_snippet: String,
+    /// bm25 score, lower is a better match
+    #[sql_type = "Double"]
+    pub rank: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Identifiable, Insertable)]
+#[table_name = "people"]
+#[primary_key(people_id)]
+pub struct Person {
+    #[column_name = "people_id"]
+    pub id: String,
+    #[column_name = "people_name"]
+    pub name: String,
+}
+
+/// A row of `movie_cast` linking a person to a movie
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
+#[table_name = "movie_cast"]
+pub struct CastMember {
+    #[column_name = "movie_cast_movies_id"]
+    pub movie_id: String,
+    #[column_name = "movie_cast_people_id"]
+    pub person_id: String,
+    #[column_name = "movie_cast_role"]
+    pub role: String,
+    #[column_name = "movie_cast_character"]
+    pub character: Option<String>,
+    #[column_name = "movie_cast_billing"]
+    pub billing: i32,
+}
+
+/// A person's credit on a movie, as listed on that movie
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+pub struct Credit {
+    pub person_id: String,
+    pub name: String,
+    pub role: String,
+    pub character: Option<String>,
+    pub billing: i32,
+}
+
+/// A movie a person is credited on, as listed on that person
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+pub struct Appearance {
+    pub movie: Movie,
+    pub role: String,
+    pub character: Option<String>,
+    pub billing: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct PersonSummary {
+    pub id: String,
+    pub name: String,
+    /// Number of movies the person is credited on
+    pub movies: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Filmography {
+    #[serde(flatten)]
+    pub person: Person,
+    pub credits: Vec<Appearance>,
+}
+
+/// One write to a movie, with the movie as it was before and after
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub movie_id: String,
+    pub action: AuditAction,
+    pub actor: String,
+    /// UTC
+    pub at: NaiveDateTime,
+    pub before: Option<Json>,
+    pub after: Option<Json>,
+    pub library_id: String,
+}
+
+/// A movie exactly as it was at one version
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+pub struct Revision {
+    pub movie_id: String,
+    pub version: i32,
+    pub actor: String,
+    /// UTC
+    pub at: NaiveDateTime,
+    pub movie: Json,
+    pub library_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Queryable, Identifiable, Insertable)]
+#[table_name = "users"]
+#[primary_key(users_id)]
+pub struct User {
+    #[column_name = "users_id"]
+    pub id: String,
+    #[column_name = "users_username"]
+    pub username: String,
+    #[column_name = "users_password_hash"]
+    #[serde(skip)]
+    pub password_hash: String,
+    /// UTC
+    #[column_name = "users_created_at"]
+    pub created_at: NaiveDateTime,
+    #[column_name = "users_role"]
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "sessions"]
+pub struct Session {
+    #[column_name = "sessions_token_hash"]
+    pub token_hash: String,
+    #[column_name = "sessions_users_id"]
+    pub user_id: String,
+    /// UTC
+    #[column_name = "sessions_created_at"]
+    pub created_at: NaiveDateTime,
+    /// UTC
+    #[column_name = "sessions_expires_at"]
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Queryable, Insertable)]
+#[table_name = "api_tokens"]
+pub struct ApiToken {
+    #[column_name = "api_tokens_id"]
+    pub id: String,
+    #[column_name = "api_tokens_users_id"]
+    #[serde(skip)]
+    pub user_id: String,
+    /// What the owner calls it, such as the script it was made for
+    #[column_name = "api_tokens_name"]
+    pub name: String,
+    #[column_name = "api_tokens_token_hash"]
+    #[serde(skip)]
+    pub token_hash: String,
+    /// Limits the token to requests a viewer could make
+    #[column_name = "api_tokens_read_only"]
+    pub read_only: bool,
+    /// UTC
+    #[column_name = "api_tokens_created_at"]
+    pub created_at: NaiveDateTime,
+    /// UTC, never when absent
+    #[column_name = "api_tokens_expires_at"]
+    pub expires_at: Option<NaiveDateTime>,
+    /// UTC
+    #[column_name = "api_tokens_last_used_at"]
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Queryable, Insertable)]
+#[table_name = "libraries"]
+pub struct Library {
+    #[column_name = "libraries_id"]
+    pub id: String,
+    #[column_name = "libraries_name"]
+    pub name: String,
+    /// UTC
+    #[column_name = "libraries_created_at"]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "library_members"]
+pub struct LibraryMember {
+    #[column_name = "library_members_libraries_id"]
+    pub library_id: String,
+    #[column_name = "library_members_users_id"]
+    pub user_id: String,
+    /// UTC
+    #[column_name = "library_members_added_at"]
+    pub added_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Queryable, Insertable)]
+#[table_name = "loans"]
+pub struct Loan {
+    #[column_name = "loans_id"]
+    pub id: String,
+    #[column_name = "loans_movies_id"]
+    pub movie_id: String,
+    #[column_name = "loans_borrower"]
+    pub borrower: String,
+    /// How to reach the borrower, free form and possibly empty
+    #[column_name = "loans_contact"]
+    pub contact: String,
+    /// UTC
+    #[column_name = "loans_lent_at"]
+    pub lent_at: NaiveDateTime,
+    /// Overdue from the day after
+    #[column_name = "loans_due_date"]
+    pub due_date: NaiveDate,
+    /// UTC, open until set
+    #[column_name = "loans_returned_at"]
+    pub returned_at: Option<NaiveDateTime>,
 }
@@ -0,0 +1,473 @@
+use ::actix::prelude::*;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::schema::movie_cast::dsl::*;
+use super::schema::people::dsl::*;
+use super::types::AuditAction;
+use super::{
+    audit, model, movie_for_write, page_size, write_transaction, DbError, DbExecutor, Precondition,
+};
+
+/// Role stored for credits coming from a movie's `actors` field
+pub const ACTOR: &str = "actor";
+
+/// Split a comma separated list of names, collapsing whitespace inside each
+/// name and dropping case-insensitive duplicates
+pub fn split_names(names: &str) -> Vec<String> {
+    let mut seen: Vec<String> = Vec::new();
+    let mut result = Vec::new();
+    for name in names.split(',') {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.is_empty() || seen.contains(&name.to_lowercase()) {
+            continue;
+        }
+        seen.push(name.to_lowercase());
+        result.push(name);
+    }
+    result
+}
+
+/// Look up a person by name, ignoring case, creating them if needed
+pub fn find_or_create(conn: &SqliteConnection, name: &str) -> QueryResult<model::Person> {
+    let existing = people
+        .filter(people_name.eq(name))
+        .first::<model::Person>(conn)
+        .optional()?;
+    if let Some(person) = existing {
+        return Ok(person);
+    }
+
+    let person = model::Person {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+    };
+    diesel::insert_into(people).values(&person).execute(conn)?;
+    Ok(person)
+}
+
+/// Resolve each name in an `actors` string to a person
+pub fn resolve_actors(conn: &SqliteConnection, actors: &str) -> QueryResult<Vec<model::Person>> {
+    split_names(actors)
+        .iter()
+        .map(|name| find_or_create(conn, name))
+        .collect()
+}
+
+/// The `actors` display string for a list of people
+pub fn display_names(cast: &[model::Person]) -> String {
+    cast.iter()
+        .map(|person| person.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Replace a movie's actor credits, keeping the character of anyone who
+/// was already credited
+pub fn replace_actors(
+    conn: &SqliteConnection,
+    movie: &str,
+    actors: &[model::Person],
+) -> QueryResult<()> {
+    let credits = movie_cast
+        .filter(movie_cast_movies_id.eq(movie))
+        .filter(movie_cast_role.eq(ACTOR));
+    let previous = credits.load::<model::CastMember>(conn)?;
+    diesel::delete(credits).execute(conn)?;
+
+    let rows = actors
+        .iter()
+        .enumerate()
+        .map(|(i, person)| model::CastMember {
+            movie_id: movie.to_string(),
+            person_id: person.id.clone(),
+            role: ACTOR.to_string(),
+            character: previous
+                .iter()
+                .find(|credit| credit.person_id == person.id)
+                .and_then(|credit| credit.character.clone()),
+            billing: i as i32 + 1,
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(movie_cast).values(&rows).execute(conn)?;
+    Ok(())
+}
+
+/// Rebuild a movie's `actors` string from its actor credits, counting it as
+/// a write to the movie by `actor`, and return the movie as it now is
+pub fn refresh_actors(
+    conn: &SqliteConnection,
+    movie: &str,
+    actor: &str,
+) -> QueryResult<model::Movie> {
+    use super::schema::movies::dsl::*;
+
+    let before = movies.filter(movies_id.eq(movie)).first::<model::Movie>(conn)?;
+
+    let names = movie_cast
+        .inner_join(people)
+        .filter(movie_cast_movies_id.eq(movie))
+        .filter(movie_cast_role.eq(ACTOR))
+        .order(movie_cast_billing.asc())
+        .select(people_name)
+        .load::<String>(conn)?;
+    diesel::update(movies.filter(movies_id.eq(movie)))
+        .set((
+            movies_actors.eq(names.join(", ")),
+            movies_version.eq(movies_version + 1),
+            movies_updated_at.eq(super::now()),
+        ))
+        .execute(conn)?;
+    let after = movies.filter(movies_id.eq(movie)).first::<model::Movie>(conn)?;
+    audit::record(conn, AuditAction::Update, actor, &before, Some(&before), Some(&after))?;
+    Ok(after)
+}
+
+fn credits_for(conn: &SqliteConnection, movie: &str) -> QueryResult<Vec<model::Credit>> {
+    movie_cast
+        .inner_join(people)
+        .filter(movie_cast_movies_id.eq(movie))
+        .order((movie_cast_billing.asc(), movie_cast_role.asc()))
+        .select((
+            people_id,
+            people_name,
+            movie_cast_role,
+            movie_cast_character,
+            movie_cast_billing,
+        ))
+        .load::<model::Credit>(conn)
+}
+
+fn movie_exists(conn: &SqliteConnection, library: &str, movie: &str) -> Result<(), DbError> {
+    use super::schema::movies::dsl::*;
+
+    movies
+        .filter(movies_id.eq(movie))
+        .filter(movies_libraries_id.eq(library))
+        .filter(movies_deleted_at.is_null())
+        .select(movies_id)
+        .first::<String>(conn)
+        .optional()?
+        .map(|_| ())
+        .ok_or_else(|| DbError::NotFound(format!("No movie with id {}", movie)))
+}
+
+/// A person credited on any movie in a library, including the trash.
+///
+/// People are shared between libraries, but each library only sees the ones
+/// its own movies mention.
+fn person_in(
+    conn: &SqliteConnection,
+    library: &str,
+    person: &str,
+) -> Result<model::Person, DbError> {
+    use super::schema::movies;
+
+    let credited = movie_cast
+        .inner_join(movies::table)
+        .filter(movie_cast_people_id.eq(person))
+        .filter(movies::movies_libraries_id.eq(library))
+        .select(movie_cast_people_id)
+        .first::<String>(conn)
+        .optional()?;
+    let found = match credited {
+        Some(_) => people.filter(people_id.eq(person)).first(conn).optional()?,
+        None => None,
+    };
+    found.ok_or_else(|| DbError::NotFound(format!("No person with id {}", person)))
+}
+
+/*
+ * List people
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GetPeople {
+    #[serde(skip)]
+    pub library: String,
+    /// Case-insensitive substring of the name
+    pub name: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Split around the library it counts in, which is bound in between
+const MOVIE_COUNT_SQL: (&str, &str) = (
+    "(SELECT count(DISTINCT movie_cast_movies_id) \
+     FROM movie_cast JOIN movies ON movies_id = movie_cast_movies_id \
+     WHERE movie_cast_people_id = people_id AND movies_libraries_id = ",
+    " AND movies_deleted_at IS NULL)",
+);
+
+impl Message for GetPeople {
+    type Result = Result<Vec<model::PersonSummary>, DbError>;
+}
+
+impl Handler<GetPeople> for DbExecutor {
+    type Result = Result<Vec<model::PersonSummary>, DbError>;
+
+    fn handle(&mut self, msg: GetPeople, _: &mut Self::Context) -> Self::Result {
+        let limit = page_size(msg.limit)?;
+        let offset = msg.offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(DbError::validation("offset must not be negative"));
+        }
+
+        use super::schema::movies;
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let count = sql::<BigInt>(MOVIE_COUNT_SQL.0)
+            .bind::<Text, _>(msg.library.clone())
+            .sql(MOVIE_COUNT_SQL.1);
+        let credited = movie_cast
+            .inner_join(movies::table)
+            .filter(movies::movies_libraries_id.eq(msg.library.clone()))
+            .select(movie_cast_people_id);
+        let mut query = people
+            .filter(people_id.eq_any(credited))
+            .select((people_id, people_name, count))
+            .order(people_name.asc())
+            .limit(limit)
+            .offset(offset)
+            .into_boxed();
+        if let Some(ref name) = msg.name {
+            query = query.filter(people_name.like(super::contains_pattern(name)).escape('\\'));
+        }
+        let items = query.load::<model::PersonSummary>(conn)?;
+
+        Ok(items)
+    }
+}
+
+/*
+ * Get a person and everything they are credited on
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetPerson {
+    #[serde(skip)]
+    pub library: String,
+    pub id: String,
+}
+
+impl Message for GetPerson {
+    type Result = Result<model::Filmography, DbError>;
+}
+
+impl Handler<GetPerson> for DbExecutor {
+    type Result = Result<model::Filmography, DbError>;
+
+    fn handle(&mut self, msg: GetPerson, _: &mut Self::Context) -> Self::Result {
+        use super::schema::movies;
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let person = person_in(conn, &msg.library, &msg.id)?;
+
+        let credits = movie_cast
+            .inner_join(movies::table)
+            .filter(movie_cast_people_id.eq(&msg.id))
+            .filter(movies::movies_libraries_id.eq(&msg.library))
+            .filter(movies::movies_deleted_at.is_null())
+            .order((movies::movies_title.asc(), movie_cast_role.asc()))
+            .select((
+                movies::all_columns,
+                movie_cast_role,
+                movie_cast_character,
+                movie_cast_billing,
+            ))
+            .load::<model::Appearance>(conn)?;
+
+        Ok(model::Filmography { person, credits })
+    }
+}
+
+/*
+ * Merge duplicate people into one
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergePeople {
+    pub library: String,
+    /// Person that is kept
+    pub into: String,
+    /// People whose credits are moved over before they are removed
+    pub from: Vec<String>,
+    pub actor: String,
+}
+
+/// Drop credits on a library's movies that the surviving person already has
+/// on the same movie in the same role, so moving the rest cannot collide on
+/// the primary key
+const DROP_DUPLICATE_CREDITS_SQL: &str = "
+DELETE FROM movie_cast
+WHERE movie_cast_people_id = ?
+  AND movie_cast_movies_id IN (SELECT movies_id FROM movies WHERE movies_libraries_id = ?)
+  AND EXISTS (
+    SELECT 1 FROM movie_cast AS kept
+    WHERE kept.movie_cast_people_id = ?
+      AND kept.movie_cast_movies_id = movie_cast.movie_cast_movies_id
+      AND kept.movie_cast_role = movie_cast.movie_cast_role
+  )";
+
+impl Message for MergePeople {
+    type Result = Result<model::Person, DbError>;
+}
+
+impl Handler<MergePeople> for DbExecutor {
+    type Result = Result<model::Person, DbError>;
+
+    /// Only credits on the library's own movies move; a merged person who is
+    /// still credited in another library is kept for that library
+    fn handle(&mut self, msg: MergePeople, _: &mut Self::Context) -> Self::Result {
+        use super::schema::movies;
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let library_movies = || {
+            movies::table
+                .filter(movies::movies_libraries_id.eq(&msg.library))
+                .select(movies::movies_id)
+        };
+        write_transaction(conn, || {
+            let kept = person_in(conn, &msg.library, &msg.into)?;
+
+            for merged in msg.from.iter().filter(|merged| **merged != kept.id) {
+                person_in(conn, &msg.library, merged)?;
+                diesel::sql_query(DROP_DUPLICATE_CREDITS_SQL)
+                    .bind::<Text, _>(merged)
+                    .bind::<Text, _>(&msg.library)
+                    .bind::<Text, _>(&kept.id)
+                    .execute(conn)?;
+                diesel::update(
+                    movie_cast
+                        .filter(movie_cast_people_id.eq(merged))
+                        .filter(movie_cast_movies_id.eq_any(library_movies())),
+                )
+                .set(movie_cast_people_id.eq(&kept.id))
+                .execute(conn)?;
+                let elsewhere = movie_cast
+                    .filter(movie_cast_people_id.eq(merged))
+                    .select(movie_cast_people_id)
+                    .first::<String>(conn)
+                    .optional()?;
+                if elsewhere.is_none() {
+                    diesel::delete(people.filter(people_id.eq(merged))).execute(conn)?;
+                }
+            }
+
+            let affected = movie_cast
+                .filter(movie_cast_people_id.eq(&kept.id))
+                .filter(movie_cast_movies_id.eq_any(library_movies()))
+                .select(movie_cast_movies_id)
+                .distinct()
+                .load::<String>(conn)?;
+            for movie in affected {
+                refresh_actors(conn, &movie, &msg.actor)?;
+            }
+
+            Ok(kept)
+        })
+    }
+}
+
+/*
+ * Get the cast of a movie
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetCast {
+    #[serde(skip)]
+    pub library: String,
+    pub id: String,
+}
+
+impl Message for GetCast {
+    type Result = Result<Vec<model::Credit>, DbError>;
+}
+
+impl Handler<GetCast> for DbExecutor {
+    type Result = Result<Vec<model::Credit>, DbError>;
+
+    fn handle(&mut self, msg: GetCast, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        movie_exists(conn, &msg.library, &msg.id)?;
+        Ok(credits_for(conn, &msg.id)?)
+    }
+}
+
+/*
+ * Replace the cast of a movie
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CastEntry {
+    pub name: String,
+    /// Defaults to `actor`
+    pub role: Option<String>,
+    pub character: Option<String>,
+}
+
+/// The full cast in billing order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetCast {
+    pub library: String,
+    pub id: String,
+    pub cast: Vec<CastEntry>,
+    pub precondition: Precondition,
+    pub actor: String,
+}
+
+/// The cast as saved, and the movie whose version it bumped
+#[derive(Debug, Clone)]
+pub struct CastSet {
+    pub cast: Vec<model::Credit>,
+    pub movie: model::Movie,
+}
+
+impl Message for SetCast {
+    type Result = Result<CastSet, DbError>;
+}
+
+impl Handler<SetCast> for DbExecutor {
+    type Result = Result<CastSet, DbError>;
+
+    fn handle(&mut self, msg: SetCast, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        write_transaction(conn, || {
+            movie_for_write(conn, &msg.library, &msg.id, &msg.precondition)?;
+            diesel::delete(movie_cast.filter(movie_cast_movies_id.eq(&msg.id))).execute(conn)?;
+
+            let mut rows: Vec<model::CastMember> = Vec::new();
+            for entry in &msg.cast {
+                let name = entry.name.split_whitespace().collect::<Vec<_>>().join(" ");
+                if name.is_empty() {
+                    return Err(DbError::validation("Cast members must have a name"));
+                }
+                let person = find_or_create(conn, &name)?;
+                let role = entry.role.clone().unwrap_or_else(|| ACTOR.to_string());
+                if rows
+                    .iter()
+                    .any(|row| row.person_id == person.id && row.role == role)
+                {
+                    continue;
+                }
+                rows.push(model::CastMember {
+                    movie_id: msg.id.clone(),
+                    person_id: person.id,
+                    role,
+                    character: entry.character.clone(),
+                    billing: rows.len() as i32 + 1,
+                });
+            }
+            diesel::insert_into(movie_cast).values(&rows).execute(conn)?;
+            let movie = refresh_actors(conn, &msg.id, &msg.actor)?;
+
+            Ok(CastSet {
+                cast: credits_for(conn, &msg.id)?,
+                movie,
+            })
+        })
+    }
+}
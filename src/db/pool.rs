@@ -0,0 +1,146 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection, Pool};
+
+/// SQLite `journal_mode` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+/// SQLite `synchronous` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+macro_rules! pragma_value {
+    ($name:ident, $kind:expr, {$($variant:ident => $text:expr,)+}) => {
+        impl $name {
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$variant => $text,)+
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                $(
+                    if value.eq_ignore_ascii_case($text) {
+                        return Ok($name::$variant);
+                    }
+                )+
+                Err(format!(
+                    "unknown {} `{}`, expected one of {}",
+                    $kind,
+                    value,
+                    [$($text,)+].join(", ")
+                ))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+pragma_value!(JournalMode, "journal mode", {
+    Delete => "delete",
+    Truncate => "truncate",
+    Persist => "persist",
+    Memory => "memory",
+    Wal => "wal",
+    Off => "off",
+});
+
+pragma_value!(Synchronous, "synchronous setting", {
+    Off => "off",
+    Normal => "normal",
+    Full => "full",
+    Extra => "extra",
+});
+
+/// Pragmas applied to every connection as it is opened.
+///
+/// The defaults suit a server with several worker threads: WAL lets readers
+/// carry on while a write is in progress, and the busy timeout makes a
+/// writer wait its turn instead of failing with `database is locked`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionOptions {
+    pub journal_mode: JournalMode,
+    pub busy_timeout: Duration,
+    pub foreign_keys: bool,
+    pub synchronous: Synchronous,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        ConnectionOptions {
+            journal_mode: JournalMode::Wal,
+            busy_timeout: Duration::from_secs(5),
+            foreign_keys: true,
+            synchronous: Synchronous::Normal,
+        }
+    }
+}
+
+impl ConnectionOptions {
+    pub fn apply(&self, conn: &SqliteConnection) -> QueryResult<()> {
+        // The busy timeout goes first so that switching the journal mode
+        // waits for other connections too
+        conn.batch_execute(&format!(
+            "PRAGMA busy_timeout = {};
+             PRAGMA journal_mode = {};
+             PRAGMA foreign_keys = {};
+             PRAGMA synchronous = {};",
+            self.busy_timeout.as_millis(),
+            self.journal_mode,
+            if self.foreign_keys { "ON" } else { "OFF" },
+            self.synchronous,
+        ))
+    }
+
+    /// Open a single connection with these options
+    pub fn establish(&self, database: &str) -> ConnectionResult<SqliteConnection> {
+        let conn = SqliteConnection::establish(database)?;
+        self.apply(&conn)
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+        Ok(conn)
+    }
+
+    /// A pool of up to `size` connections with these options
+    pub fn pool(
+        &self,
+        database: &str,
+        size: u32,
+    ) -> Result<Pool<ConnectionManager<SqliteConnection>>, r2d2::PoolError> {
+        Pool::builder()
+            .max_size(size)
+            .connection_customizer(Box::new(self.clone()))
+            .build(ConnectionManager::<SqliteConnection>::new(database))
+    }
+}
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        self.apply(conn).map_err(r2d2::Error::QueryError)
+    }
+}
@@ -0,0 +1,179 @@
+use ::actix::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+use super::schema::movie_revisions::dsl::*;
+use super::types::{AuditAction, Json};
+use super::{audit, model, now, people, write_transaction, DbError, DbExecutor};
+use crate::validation::Columns;
+
+/// Keep `movie` as the revision for its current version
+pub fn save(conn: &SqliteConnection, movie: &model::Movie, actor: &str) -> QueryResult<()> {
+    diesel::insert_into(movie_revisions)
+        .values((
+            movie_revisions_movies_id.eq(&movie.id),
+            movie_revisions_version.eq(movie.version),
+            movie_revisions_actor.eq(actor),
+            movie_revisions_at.eq(now()),
+            movie_revisions_movie.eq(audit::snapshot(movie)?),
+            movie_revisions_libraries_id.eq(&movie.library_id),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// The version after the newest revision of a movie, so versions are never
+/// reused even when a purged id is created again
+pub fn next_version(conn: &SqliteConnection, movie: &str) -> QueryResult<i32> {
+    let newest = movie_revisions
+        .filter(movie_revisions_movies_id.eq(movie))
+        .select(diesel::dsl::max(movie_revisions_version))
+        .first::<Option<i32>>(conn)?;
+    Ok(newest.unwrap_or(0) + 1)
+}
+
+/*
+ * List the revisions of a movie
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetRevisions {
+    #[serde(skip)]
+    pub library: String,
+    pub id: String,
+}
+
+impl Message for GetRevisions {
+    type Result = Result<Vec<model::Revision>, DbError>;
+}
+
+impl Handler<GetRevisions> for DbExecutor {
+    type Result = Result<Vec<model::Revision>, DbError>;
+
+    fn handle(&mut self, msg: GetRevisions, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let revisions = movie_revisions
+            .filter(movie_revisions_movies_id.eq(&msg.id))
+            .filter(movie_revisions_libraries_id.eq(&msg.library))
+            .order(movie_revisions_version.desc())
+            .load::<model::Revision>(conn)?;
+        if revisions.is_empty() {
+            return Err(DbError::NotFound(format!("No movie with id {}", msg.id)));
+        }
+
+        Ok(revisions)
+    }
+}
+
+/*
+ * Bring a movie back to an earlier revision
+ */
+/// The movie is rewritten with the revision's fields as a new version, which
+/// also takes it out of the trash or recreates it if it was purged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevertMovie {
+    pub library: String,
+    pub id: String,
+    pub version: i32,
+    /// The revision's column must still be one of these, unless the movie is
+    /// in it already
+    #[serde(skip)]
+    pub columns: Columns,
+    pub actor: String,
+}
+
+impl Message for RevertMovie {
+    type Result = Result<model::Movie, DbError>;
+}
+
+impl Handler<RevertMovie> for DbExecutor {
+    type Result = Result<model::Movie, DbError>;
+
+    fn handle(&mut self, msg: RevertMovie, _: &mut Self::Context) -> Self::Result {
+        use super::schema::movies::dsl::*;
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        write_transaction(conn, || {
+            let Json(snapshot) = movie_revisions
+                .filter(movie_revisions_movies_id.eq(&msg.id))
+                .filter(movie_revisions_version.eq(msg.version))
+                .filter(movie_revisions_libraries_id.eq(&msg.library))
+                .select(movie_revisions_movie)
+                .first::<Json>(conn)
+                .optional()?
+                .ok_or_else(|| {
+                    DbError::NotFound(format!(
+                        "Movie {} has no revision {}",
+                        msg.id, msg.version
+                    ))
+                })?;
+            let revision: model::Movie = serde_json::from_value(snapshot).map_err(|e| {
+                DbError::Database(format!("Revision {} is unreadable: {}", msg.version, e))
+            })?;
+
+            // A purged movie's id may since have been taken in another library
+            let in_use = movies
+                .filter(movies_id.eq(&msg.id))
+                .filter(movies_libraries_id.ne(&msg.library))
+                .select(movies_id)
+                .first::<String>(conn)
+                .optional()?;
+            if in_use.is_some() {
+                return Err(DbError::Conflict(format!("The id {} is already in use", msg.id)));
+            }
+            let current = movies
+                .filter(movies_id.eq(&msg.id))
+                .filter(movies_libraries_id.eq(&msg.library))
+                .first::<model::Movie>(conn)
+                .optional()?;
+            let column = match msg.columns.check(&revision.column) {
+                Ok(column) => column,
+                Err(_) if current.as_ref().is_some_and(|m| m.column == revision.column) => {
+                    revision.column.clone()
+                }
+                Err(message) => return Err(DbError::field("column", message)),
+            };
+            let cast = people::resolve_actors(conn, &revision.actors)?;
+            let reverted = model::Movie {
+                actors: people::display_names(&cast),
+                version: next_version(conn, &msg.id)?,
+                created_at: current
+                    .as_ref()
+                    .map_or(revision.created_at, |movie| movie.created_at),
+                updated_at: now(),
+                deleted_at: None,
+                library_id: msg.library.clone(),
+                // Whether the disc is lent out is not part of its history
+                on_loan: current.as_ref().is_some_and(|movie| movie.on_loan),
+                column,
+                ..revision
+            };
+
+            match current {
+                Some(_) => {
+                    // `None` fields are skipped by the changeset, so clear the
+                    // trash marker explicitly
+                    diesel::update(movies.filter(movies_id.eq(&msg.id)))
+                        .set((&reverted, movies_deleted_at.eq(None::<NaiveDateTime>)))
+                        .execute(conn)?;
+                }
+                None => {
+                    diesel::insert_into(movies).values(&reverted).execute(conn)?;
+                }
+            }
+            people::replace_actors(conn, &msg.id, &cast)?;
+            audit::record(
+                conn,
+                AuditAction::Revert,
+                &msg.actor,
+                &reverted,
+                current.as_ref(),
+                Some(&reverted),
+            )?;
+
+            Ok(reverted)
+        })
+    }
+}
@@ -11,5 +11,131 @@ table! {
         movies_actors -> Text,
         movies_drawer -> Text,
         movies_column -> Text,
+        movies_version -> Integer,
+        movies_created_at -> Timestamp,
+        movies_updated_at -> Timestamp,
+        movies_deleted_at -> Nullable<Timestamp>,
+        movies_libraries_id -> Text,
+        movies_on_loan -> Bool,
     }
 }
+
+table! {
+    schema_version (schema_version_version) {
+        schema_version_version -> Integer,
+        schema_version_name -> Text,
+        schema_version_applied_at -> Timestamp,
+    }
+}
+
+table! {
+    people (people_id) {
+        people_id -> Text,
+        people_name -> Text,
+    }
+}
+
+table! {
+    movie_cast (movie_cast_movies_id, movie_cast_people_id, movie_cast_role) {
+        movie_cast_movies_id -> Text,
+        movie_cast_people_id -> Text,
+        movie_cast_role -> Text,
+        movie_cast_character -> Nullable<Text>,
+        movie_cast_billing -> Integer,
+    }
+}
+
+table! {
+    audit_log (audit_log_id) {
+        audit_log_id -> Integer,
+        audit_log_movies_id -> Text,
+        audit_log_action -> Text,
+        audit_log_actor -> Text,
+        audit_log_at -> Timestamp,
+        audit_log_before -> Nullable<Text>,
+        audit_log_after -> Nullable<Text>,
+        audit_log_libraries_id -> Text,
+    }
+}
+
+table! {
+    movie_revisions (movie_revisions_movies_id, movie_revisions_version) {
+        movie_revisions_movies_id -> Text,
+        movie_revisions_version -> Integer,
+        movie_revisions_actor -> Text,
+        movie_revisions_at -> Timestamp,
+        movie_revisions_movie -> Text,
+        movie_revisions_libraries_id -> Text,
+    }
+}
+
+table! {
+    users (users_id) {
+        users_id -> Text,
+        users_username -> Text,
+        users_password_hash -> Text,
+        users_created_at -> Timestamp,
+        users_role -> Text,
+    }
+}
+
+table! {
+    sessions (sessions_token_hash) {
+        sessions_token_hash -> Text,
+        sessions_users_id -> Text,
+        sessions_created_at -> Timestamp,
+        sessions_expires_at -> Timestamp,
+    }
+}
+
+table! {
+    api_tokens (api_tokens_id) {
+        api_tokens_id -> Text,
+        api_tokens_users_id -> Text,
+        api_tokens_name -> Text,
+        api_tokens_token_hash -> Text,
+        api_tokens_read_only -> Bool,
+        api_tokens_created_at -> Timestamp,
+        api_tokens_expires_at -> Nullable<Timestamp>,
+        api_tokens_last_used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    libraries (libraries_id) {
+        libraries_id -> Text,
+        libraries_name -> Text,
+        libraries_created_at -> Timestamp,
+    }
+}
+
+table! {
+    library_members (library_members_libraries_id, library_members_users_id) {
+        library_members_libraries_id -> Text,
+        library_members_users_id -> Text,
+        library_members_added_at -> Timestamp,
+    }
+}
+
+table! {
+    loans (loans_id) {
+        loans_id -> Text,
+        loans_movies_id -> Text,
+        loans_borrower -> Text,
+        loans_contact -> Text,
+        loans_lent_at -> Timestamp,
+        loans_due_date -> Date,
+        loans_returned_at -> Nullable<Timestamp>,
+    }
+}
+
+joinable!(movie_cast -> movies (movie_cast_movies_id));
+joinable!(movie_cast -> people (movie_cast_people_id));
+joinable!(loans -> movies (loans_movies_id));
+joinable!(sessions -> users (sessions_users_id));
+joinable!(api_tokens -> users (api_tokens_users_id));
+joinable!(library_members -> libraries (library_members_libraries_id));
+joinable!(library_members -> users (library_members_users_id));
+
+allow_tables_to_appear_in_same_query!(movies, people, movie_cast, loans);
+allow_tables_to_appear_in_same_query!(users, sessions, api_tokens, libraries, library_members);
@@ -0,0 +1,183 @@
+use ::actix::prelude::*;
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use serde_derive::Serialize;
+use uuid::Uuid;
+
+use super::schema::api_tokens::dsl::*;
+use super::schema::users;
+use super::users::{hash_token, new_token};
+use super::{model, now, write_transaction, DbError, DbExecutor};
+
+/// How stale `last_used_at` may get before a request updates it, so that a
+/// busy script does not turn every read into a write
+fn last_used_resolution() -> Duration {
+    Duration::minutes(1)
+}
+
+/*
+ * Create a token
+ */
+#[derive(Debug, Clone)]
+pub struct CreateToken {
+    pub user_id: String,
+    pub name: String,
+    pub read_only: bool,
+    /// UTC, never when absent
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// A new token; `token` is only ever shown here
+#[derive(Debug, Clone, Serialize)]
+pub struct NewToken {
+    #[serde(flatten)]
+    pub info: model::ApiToken,
+    pub token: String,
+}
+
+impl Message for CreateToken {
+    type Result = Result<NewToken, DbError>;
+}
+
+impl Handler<CreateToken> for DbExecutor {
+    type Result = Result<NewToken, DbError>;
+
+    fn handle(&mut self, msg: CreateToken, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let token = new_token()?;
+        let info = model::ApiToken {
+            id: Uuid::new_v4().to_hyphenated().to_string(),
+            user_id: msg.user_id,
+            name: msg.name,
+            token_hash: hash_token(&token),
+            read_only: msg.read_only,
+            created_at: now(),
+            expires_at: msg.expires_at,
+            last_used_at: None,
+        };
+        write_transaction(conn, || {
+            diesel::insert_into(api_tokens).values(&info).execute(conn)
+        })?;
+        Ok(NewToken { info, token })
+    }
+}
+
+/*
+ * List and revoke a user's tokens
+ */
+#[derive(Debug, Clone)]
+pub struct ListTokens {
+    pub user_id: String,
+}
+
+impl Message for ListTokens {
+    type Result = Result<Vec<model::ApiToken>, DbError>;
+}
+
+impl Handler<ListTokens> for DbExecutor {
+    type Result = Result<Vec<model::ApiToken>, DbError>;
+
+    fn handle(&mut self, msg: ListTokens, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let items = api_tokens
+            .filter(api_tokens_users_id.eq(&msg.user_id))
+            .order(api_tokens_created_at.desc())
+            .load::<model::ApiToken>(conn)?;
+
+        Ok(items)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RevokeToken {
+    pub user_id: String,
+    pub id: String,
+}
+
+impl Message for RevokeToken {
+    type Result = Result<(), DbError>;
+}
+
+impl Handler<RevokeToken> for DbExecutor {
+    type Result = Result<(), DbError>;
+
+    fn handle(&mut self, msg: RevokeToken, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let deleted = write_transaction(conn, || {
+            diesel::delete(
+                api_tokens
+                    .filter(api_tokens_id.eq(&msg.id))
+                    .filter(api_tokens_users_id.eq(&msg.user_id)),
+            )
+            .execute(conn)
+        })?;
+        if deleted == 0 {
+            return Err(DbError::NotFound(format!("No token with id {}", msg.id)));
+        }
+        Ok(())
+    }
+}
+
+/*
+ * Look up who a bearer token belongs to
+ */
+#[derive(Debug, Clone)]
+pub struct AuthenticateToken {
+    pub token: String,
+}
+
+/// The owner of a token and whether the token may only read
+#[derive(Debug, Clone)]
+pub struct TokenOwner {
+    pub user: model::User,
+    pub read_only: bool,
+}
+
+impl Message for AuthenticateToken {
+    type Result = Result<TokenOwner, DbError>;
+}
+
+impl Handler<AuthenticateToken> for DbExecutor {
+    type Result = Result<TokenOwner, DbError>;
+
+    fn handle(&mut self, msg: AuthenticateToken, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let found = api_tokens
+            .inner_join(users::table)
+            .filter(api_tokens_token_hash.eq(hash_token(&msg.token)))
+            .filter(
+                api_tokens_expires_at
+                    .is_null()
+                    .or(api_tokens_expires_at.gt(now())),
+            )
+            .select((api_tokens_id, api_tokens_read_only, users::all_columns))
+            .first::<(String, bool, model::User)>(conn)
+            .optional()?;
+        let (token_id, read_only, user) = match found {
+            Some(found) => found,
+            None => {
+                return Err(DbError::Unauthorized(
+                    "The API token is unknown, revoked or expired".to_string(),
+                ))
+            }
+        };
+
+        let stale = now() - last_used_resolution();
+        write_transaction(conn, || {
+            diesel::update(
+                api_tokens.filter(api_tokens_id.eq(&token_id)).filter(
+                    api_tokens_last_used_at
+                        .is_null()
+                        .or(api_tokens_last_used_at.lt(stale)),
+                ),
+            )
+            .set(api_tokens_last_used_at.eq(now()))
+            .execute(conn)
+        })?;
+        Ok(TokenOwner { user, read_only })
+    }
+}
@@ -0,0 +1,130 @@
+use ::actix::prelude::*;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+use super::schema::movies::dsl::*;
+use super::types::AuditAction;
+use super::{audit, model, now, page_size, write_transaction, DbError, DbExecutor};
+
+/// Movies that have been in the trash since before `cutoff`, oldest first
+pub fn expired(conn: &SqliteConnection, cutoff: NaiveDateTime) -> QueryResult<Vec<model::Movie>> {
+    movies
+        .filter(movies_deleted_at.lt(cutoff))
+        .order(movies_deleted_at.asc())
+        .load::<model::Movie>(conn)
+}
+
+/// Permanently delete movies trashed before `cutoff`, along with their cast
+/// and loans, returning what was removed
+pub fn purge(
+    conn: &SqliteConnection,
+    cutoff: NaiveDateTime,
+    actor: &str,
+) -> QueryResult<Vec<model::Movie>> {
+    use super::schema::loans::dsl::*;
+    use super::schema::movie_cast::dsl::*;
+
+    write_transaction(conn, || {
+        let purged = expired(conn, cutoff)?;
+        let ids = purged.iter().map(|movie| movie.id.as_str()).collect::<Vec<_>>();
+        diesel::delete(movie_cast.filter(movie_cast_movies_id.eq_any(&ids))).execute(conn)?;
+        diesel::delete(loans.filter(loans_movies_id.eq_any(&ids))).execute(conn)?;
+        diesel::delete(movies.filter(movies_id.eq_any(&ids))).execute(conn)?;
+        for movie in &purged {
+            audit::record(conn, AuditAction::Purge, actor, movie, Some(movie), None)?;
+        }
+        Ok(purged)
+    })
+}
+
+/*
+ * List the trash
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GetTrash {
+    #[serde(skip)]
+    pub library: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl Message for GetTrash {
+    type Result = Result<Vec<model::Movie>, DbError>;
+}
+
+impl Handler<GetTrash> for DbExecutor {
+    type Result = Result<Vec<model::Movie>, DbError>;
+
+    fn handle(&mut self, msg: GetTrash, _: &mut Self::Context) -> Self::Result {
+        let limit = page_size(msg.limit)?;
+        let offset = msg.offset.unwrap_or(0);
+        if offset < 0 {
+            return Err(DbError::validation("offset must not be negative"));
+        }
+
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let items = movies
+            .filter(movies_libraries_id.eq(&msg.library))
+            .filter(movies_deleted_at.is_not_null())
+            .order((movies_deleted_at.desc(), movies_id.asc()))
+            .limit(limit)
+            .offset(offset)
+            .load::<model::Movie>(conn)?;
+
+        Ok(items)
+    }
+}
+
+/*
+ * Take a movie back out of the trash
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreMovie {
+    pub library: String,
+    pub id: String,
+    pub actor: String,
+}
+
+impl Message for RestoreMovie {
+    type Result = Result<model::Movie, DbError>;
+}
+
+impl Handler<RestoreMovie> for DbExecutor {
+    type Result = Result<model::Movie, DbError>;
+
+    fn handle(&mut self, msg: RestoreMovie, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        write_transaction(conn, || {
+            let trashed = movies
+                .filter(movies_id.eq(&msg.id))
+                .filter(movies_libraries_id.eq(&msg.library))
+                .filter(movies_deleted_at.is_not_null())
+                .first::<model::Movie>(conn)
+                .optional()?
+                .ok_or_else(|| {
+                    DbError::NotFound(format!("No movie with id {} in the trash", msg.id))
+                })?;
+            diesel::update(movies.filter(movies_id.eq(&msg.id)))
+                .set((
+                    movies_deleted_at.eq(None::<NaiveDateTime>),
+                    movies_version.eq(movies_version + 1),
+                    movies_updated_at.eq(now()),
+                ))
+                .execute(conn)?;
+
+            let restored = movies.filter(movies_id.eq(&msg.id)).first(conn)?;
+            audit::record(
+                conn,
+                AuditAction::Restore,
+                &msg.actor,
+                &restored,
+                Some(&trashed),
+                Some(&restored),
+            )?;
+            Ok(restored)
+        })
+    }
+}
@@ -0,0 +1,285 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::Sqlite;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_json::Value;
+
+/// A value that is not one of an enum's accepted spellings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownValue {
+    pub kind: &'static str,
+    pub value: String,
+    pub expected: &'static [&'static str],
+}
+
+impl fmt::Display for UnknownValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown {} `{}`, expected one of {}",
+            self.kind,
+            self.value,
+            self.expected.join(", ")
+        )
+    }
+}
+
+impl Error for UnknownValue {}
+
+/// Reduce a spelling to lowercase letters and digits so that "Blu-ray",
+/// "BLU RAY" and "bluray" compare equal
+fn normalize(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Define a closed set of values stored as their canonical text in SQLite.
+///
+/// Each variant lists its canonical spelling followed by any other accepted
+/// spellings, compared after `normalize`.
+macro_rules! text_enum {
+    ($(#[$meta:meta])* $name:ident, $kind:expr, {
+        $($variant:ident => $canonical:expr $(, $alias:expr)*;)+
+    }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+        #[sql_type = "Text"]
+        pub enum $name {
+            $($variant,)+
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant,)+];
+            const CANONICAL: &'static [&'static str] = &[$($canonical,)+];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$variant => $canonical,)+
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = UnknownValue;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                let key = normalize(value);
+                $(
+                    if key == normalize($canonical) $(|| key == normalize($alias))* {
+                        return Ok($name::$variant);
+                    }
+                )+
+                Err(UnknownValue {
+                    kind: $kind,
+                    value: value.to_string(),
+                    expected: $name::CANONICAL,
+                })
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = String::deserialize(deserializer)?;
+                value.parse().map_err(de::Error::custom)
+            }
+        }
+
+        impl ToSql<Text, Sqlite> for $name {
+            fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+                ToSql::<Text, Sqlite>::to_sql(self.as_str(), out)
+            }
+        }
+
+        impl FromSql<Text, Sqlite> for $name {
+            fn from_sql(bytes: Option<&<Sqlite as Backend>::RawValue>) -> deserialize::Result<Self> {
+                let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+                Ok(value.parse()?)
+            }
+        }
+    };
+}
+
+text_enum!(
+    /// MPAA film ratings and US TV parental guidelines
+    Rating, "rating", {
+        G => "G";
+        Pg => "PG";
+        Pg13 => "PG-13";
+        R => "R";
+        Nc17 => "NC-17";
+        NotRated => "NR", "Not Rated", "Unrated";
+        TvY => "TV-Y";
+        TvY7 => "TV-Y7";
+        TvG => "TV-G";
+        TvPg => "TV-PG";
+        Tv14 => "TV-14";
+        TvMa => "TV-MA";
+    }
+);
+
+text_enum!(
+    /// Physical or digital release format
+    Format, "format", {
+        Dvd => "DVD";
+        BluRay => "Blu-ray", "BD";
+        UhdBluRay => "4K UHD", "4K", "UHD", "4K Blu-ray", "UHD Blu-ray";
+        Vhs => "VHS";
+        Digital => "Digital", "Digital Copy";
+        Other => "Other";
+    }
+);
+
+text_enum!(
+    /// Picture framing of the release
+    Aspect, "aspect", {
+        Widescreen => "Widescreen", "WS", "Anamorphic", "Anamorphic Widescreen", "Letterbox";
+        Fullscreen => "Fullscreen", "FS", "Full", "Pan & Scan", "Pan and Scan";
+        Both => "Widescreen & Fullscreen", "Both", "Widescreen/Fullscreen";
+        Other => "Other";
+    }
+);
+
+text_enum!(
+    /// Kind of write recorded in the audit log
+    AuditAction, "action", {
+        Create => "create";
+        Update => "update";
+        Delete => "delete";
+        Restore => "restore";
+        Purge => "purge";
+        Revert => "revert";
+        CheckOut => "check_out";
+        CheckIn => "check_in";
+    }
+);
+
+text_enum!(
+    /// What a user may do, each role including everything below it
+    Role, "role", {
+        Viewer => "viewer";
+        Editor => "editor";
+        Admin => "admin";
+    }
+);
+
+impl Role {
+    /// Whether this role includes everything `required` may do
+    pub fn allows(self, required: Role) -> bool {
+        let rank = |role| Role::ALL.iter().position(|r| *r == role);
+        rank(self) >= rank(required)
+    }
+}
+
+/// A JSON document stored as text
+#[derive(Debug, Clone, PartialEq, AsExpression, FromSqlRow)]
+#[sql_type = "Text"]
+pub struct Json(pub Value);
+
+impl Serialize for Json {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Json {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Value::deserialize(deserializer).map(Json)
+    }
+}
+
+impl ToSql<Text, Sqlite> for Json {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        let text = serde_json::to_string(&self.0)?;
+        ToSql::<Text, Sqlite>::to_sql(&text, out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Json {
+    fn from_sql(bytes: Option<&<Sqlite as Backend>::RawValue>) -> deserialize::Result<Self> {
+        let text = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(Json(serde_json::from_str(&text)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_include_the_ones_below_them() {
+        assert!(Role::Admin.allows(Role::Admin));
+        assert!(Role::Admin.allows(Role::Editor));
+        assert!(Role::Admin.allows(Role::Viewer));
+        assert!(Role::Editor.allows(Role::Editor));
+        assert!(Role::Editor.allows(Role::Viewer));
+        assert!(Role::Viewer.allows(Role::Viewer));
+    }
+
+    #[test]
+    fn roles_exclude_the_ones_above_them() {
+        assert!(!Role::Editor.allows(Role::Admin));
+        assert!(!Role::Viewer.allows(Role::Editor));
+        assert!(!Role::Viewer.allows(Role::Admin));
+    }
+
+    #[test]
+    fn aliases_parse_to_their_variant() {
+        assert_eq!("Blu-ray".parse(), Ok(Format::BluRay));
+        assert_eq!("BD".parse(), Ok(Format::BluRay));
+        assert_eq!("blu ray".parse(), Ok(Format::BluRay));
+        assert_eq!("4K Blu-ray".parse(), Ok(Format::UhdBluRay));
+        assert_eq!("Pan & Scan".parse(), Ok(Aspect::Fullscreen));
+        assert_eq!("pan and scan".parse(), Ok(Aspect::Fullscreen));
+        assert_eq!("Widescreen/Fullscreen".parse(), Ok(Aspect::Both));
+        assert_eq!("Unrated".parse(), Ok(Rating::NotRated));
+        assert_eq!("pg13".parse(), Ok(Rating::Pg13));
+    }
+
+    #[test]
+    fn every_canonical_spelling_parses_back() {
+        for rating in Rating::ALL {
+            assert_eq!(rating.as_str().parse(), Ok(*rating));
+        }
+        for format in Format::ALL {
+            assert_eq!(format.as_str().parse(), Ok(*format));
+        }
+        for aspect in Aspect::ALL {
+            assert_eq!(aspect.as_str().parse(), Ok(*aspect));
+        }
+    }
+
+    #[test]
+    fn unknown_values_are_rejected() {
+        let e = "Betamax".parse::<Format>().unwrap_err();
+        assert_eq!(e.kind, "format");
+        assert_eq!(e.value, "Betamax");
+        assert!(e.to_string().starts_with("unknown format `Betamax`, expected one of DVD,"));
+
+        assert!("".parse::<Format>().is_err());
+        assert!("PG-15".parse::<Rating>().is_err());
+        assert!("Scan".parse::<Aspect>().is_err());
+    }
+}
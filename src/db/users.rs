@@ -0,0 +1,359 @@
+use ::actix::prelude::*;
+use argon2::{Config, ThreadMode, Variant, Version};
+use chrono::Duration;
+use diesel::prelude::*;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use uuid::Uuid;
+
+use super::schema::api_tokens::dsl::{api_tokens, api_tokens_users_id};
+use super::schema::library_members::dsl::{library_members, library_members_users_id};
+use super::schema::sessions::dsl::*;
+use super::schema::users::dsl::*;
+use super::schema::users;
+use super::types::Role;
+use super::{model, now, write_transaction, DbError, DbExecutor};
+
+/// Argon2id with the OWASP recommended minimum of 19 MiB and two passes
+fn argon2_config<'a>() -> Config<'a> {
+    Config {
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        mem_cost: 19 * 1024,
+        time_cost: 2,
+        lanes: 1,
+        thread_mode: ThreadMode::Sequential,
+        secret: &[],
+        ad: &[],
+        hash_length: 32,
+    }
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, DbError> {
+    let mut bytes = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| DbError::Database("Unable to generate random bytes".to_string()))?;
+    Ok(bytes)
+}
+
+/// Hash a password with a fresh salt into Argon2's encoded form
+pub fn hash_password(password: &str) -> Result<String, DbError> {
+    let salt = random_bytes(16)?;
+    argon2::hash_encoded(password.as_bytes(), &salt, &argon2_config())
+        .map_err(|e| DbError::Database(format!("Unable to hash password: {}", e)))
+}
+
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+}
+
+/// A new unguessable bearer secret, URL safe
+pub fn new_token() -> Result<String, DbError> {
+    Ok(base64::encode_config(&random_bytes(32)?, base64::URL_SAFE_NO_PAD))
+}
+
+/// The form a token is stored in
+pub fn hash_token(token: &str) -> String {
+    digest::digest(&digest::SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Checked against when a username does not exist, so that a failed login
+/// takes as long whether or not the account is real
+const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$+Y/yyE8PxpaDs00x5QoUag$\
+                          IFU1NIII/dwjc3fGK7EhRZa++p8uebkjMsaWFnamqWY";
+
+fn unauthorized() -> DbError {
+    DbError::Unauthorized("Log in to use the API".to_string())
+}
+
+/*
+ * Create a user
+ */
+#[derive(Clone)]
+pub struct CreateUser {
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+}
+
+impl Message for CreateUser {
+    type Result = Result<model::User, DbError>;
+}
+
+impl Handler<CreateUser> for DbExecutor {
+    type Result = Result<model::User, DbError>;
+
+    fn handle(&mut self, msg: CreateUser, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        create_user(conn, &msg)
+    }
+}
+
+pub fn create_user(conn: &SqliteConnection, msg: &CreateUser) -> Result<model::User, DbError> {
+    let user = model::User {
+        id: Uuid::new_v4().to_hyphenated().to_string(),
+        username: msg.username.clone(),
+        password_hash: hash_password(&msg.password)?,
+        created_at: now(),
+        role: msg.role,
+    };
+    write_transaction(conn, || {
+        let taken = users
+            .filter(users_username.eq(&user.username))
+            .select(users_id)
+            .first::<String>(conn)
+            .optional()?;
+        if taken.is_some() {
+            return Err(DbError::Conflict(format!(
+                "The username {} is already taken",
+                user.username
+            )));
+        }
+        diesel::insert_into(users).values(&user).execute(conn)?;
+        Ok(user.clone())
+    })
+}
+
+/*
+ * Log in and out
+ */
+/// Deliberately not `Debug`, to keep the password out of logs
+#[derive(Clone)]
+pub struct Login {
+    pub username: String,
+    pub password: String,
+    /// How long the session lasts
+    pub ttl: Duration,
+}
+
+/// A new session; `token` goes back to the client and is never stored
+#[derive(Debug, Clone)]
+pub struct LoggedIn {
+    pub user: model::User,
+    pub token: String,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+impl Message for Login {
+    type Result = Result<LoggedIn, DbError>;
+}
+
+impl Handler<Login> for DbExecutor {
+    type Result = Result<LoggedIn, DbError>;
+
+    fn handle(&mut self, msg: Login, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let user = users
+            .filter(users_username.eq(msg.username.trim()))
+            .first::<model::User>(conn)
+            .optional()?;
+        let hash = user.as_ref().map(|user| user.password_hash.as_str());
+        let verified = verify_password(hash.unwrap_or(DUMMY_HASH), &msg.password);
+        let user = match user {
+            Some(user) if verified => user,
+            _ => {
+                return Err(DbError::Unauthorized(
+                    "Wrong username or password".to_string(),
+                ))
+            }
+        };
+
+        let expires_at = now()
+            .checked_add_signed(msg.ttl)
+            .ok_or_else(|| DbError::validation("The session length is too long"))?;
+        let token = new_token()?;
+        let session = model::Session {
+            token_hash: hash_token(&token),
+            user_id: user.id.clone(),
+            created_at: now(),
+            expires_at,
+        };
+        write_transaction(conn, || {
+            diesel::delete(sessions.filter(sessions_expires_at.le(now()))).execute(conn)?;
+            diesel::insert_into(sessions).values(&session).execute(conn)
+        })?;
+        Ok(LoggedIn {
+            user,
+            token,
+            expires_at: session.expires_at,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Logout {
+    pub token: String,
+}
+
+impl Message for Logout {
+    type Result = Result<(), DbError>;
+}
+
+impl Handler<Logout> for DbExecutor {
+    type Result = Result<(), DbError>;
+
+    fn handle(&mut self, msg: Logout, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        diesel::delete(sessions.filter(sessions_token_hash.eq(hash_token(&msg.token))))
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+/*
+ * Look up who a session belongs to
+ */
+#[derive(Debug, Clone)]
+pub struct Authenticate {
+    pub token: String,
+}
+
+impl Message for Authenticate {
+    type Result = Result<model::User, DbError>;
+}
+
+impl Handler<Authenticate> for DbExecutor {
+    type Result = Result<model::User, DbError>;
+
+    fn handle(&mut self, msg: Authenticate, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        sessions
+            .inner_join(users)
+            .filter(sessions_token_hash.eq(hash_token(&msg.token)))
+            .filter(sessions_expires_at.gt(now()))
+            .select(users::all_columns)
+            .first::<model::User>(conn)
+            .optional()?
+            .ok_or_else(unauthorized)
+    }
+}
+
+/*
+ * Manage users
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ListUsers;
+
+impl Message for ListUsers {
+    type Result = Result<Vec<model::User>, DbError>;
+}
+
+impl Handler<ListUsers> for DbExecutor {
+    type Result = Result<Vec<model::User>, DbError>;
+
+    fn handle(&mut self, _: ListUsers, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let items = users.order(users_username.asc()).load::<model::User>(conn)?;
+
+        Ok(items)
+    }
+}
+
+fn find_user(conn: &SqliteConnection, id: &str) -> Result<model::User, DbError> {
+    users
+        .filter(users_id.eq(id))
+        .first::<model::User>(conn)
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("No user with id {}", id)))
+}
+
+/// Refuse to leave the server without anyone able to manage it
+fn keep_an_admin(conn: &SqliteConnection, user: &model::User) -> Result<(), DbError> {
+    if user.role != Role::Admin {
+        return Ok(());
+    }
+    let others = users
+        .filter(users_role.eq(Role::Admin))
+        .filter(users_id.ne(&user.id))
+        .count()
+        .get_result::<i64>(conn)?;
+    if others == 0 {
+        return Err(DbError::Conflict(format!(
+            "{} is the only admin",
+            user.username
+        )));
+    }
+    Ok(())
+}
+
+/// Change a user's role or password. A new password logs the user out
+/// everywhere.
+#[derive(Clone)]
+pub struct UpdateUser {
+    pub id: String,
+    pub role: Option<Role>,
+    pub password: Option<String>,
+}
+
+impl Message for UpdateUser {
+    type Result = Result<model::User, DbError>;
+}
+
+impl Handler<UpdateUser> for DbExecutor {
+    type Result = Result<model::User, DbError>;
+
+    fn handle(&mut self, msg: UpdateUser, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        let hash = match msg.password {
+            Some(ref password) => Some(hash_password(password)?),
+            None => None,
+        };
+        write_transaction(conn, || {
+            let user = find_user(conn, &msg.id)?;
+            if let Some(role) = msg.role {
+                if role != Role::Admin {
+                    keep_an_admin(conn, &user)?;
+                }
+                diesel::update(users.filter(users_id.eq(&msg.id)))
+                    .set(users_role.eq(role))
+                    .execute(conn)?;
+            }
+            if let Some(ref hash) = hash {
+                diesel::update(users.filter(users_id.eq(&msg.id)))
+                    .set(users_password_hash.eq(hash))
+                    .execute(conn)?;
+                diesel::delete(sessions.filter(sessions_users_id.eq(&msg.id))).execute(conn)?;
+            }
+            find_user(conn, &msg.id)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeleteUser {
+    pub id: String,
+}
+
+impl Message for DeleteUser {
+    type Result = Result<model::User, DbError>;
+}
+
+impl Handler<DeleteUser> for DbExecutor {
+    type Result = Result<model::User, DbError>;
+
+    fn handle(&mut self, msg: DeleteUser, _: &mut Self::Context) -> Self::Result {
+        let conn: &SqliteConnection = &*self.0.get()?;
+
+        write_transaction(conn, || {
+            let user = find_user(conn, &msg.id)?;
+            keep_an_admin(conn, &user)?;
+            diesel::delete(sessions.filter(sessions_users_id.eq(&msg.id))).execute(conn)?;
+            diesel::delete(api_tokens.filter(api_tokens_users_id.eq(&msg.id))).execute(conn)?;
+            diesel::delete(library_members.filter(library_members_users_id.eq(&msg.id)))
+                .execute(conn)?;
+            diesel::delete(users.filter(users_id.eq(&msg.id))).execute(conn)?;
+            Ok(user)
+        })
+    }
+}
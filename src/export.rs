@@ -0,0 +1,106 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::db::{model, DbError};
+
+/// File formats movies can be exported as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One row per movie under a header naming the fields, readable by the
+    /// CSV importer
+    Csv,
+    /// A single array of movies
+    #[default]
+    Json,
+    /// One movie object per line
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// Bytes written before the first movie
+    pub fn start(self) -> Vec<u8> {
+        match self {
+            ExportFormat::Json => b"[".to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Bytes written after the last movie
+    pub fn end(self) -> Vec<u8> {
+        match self {
+            ExportFormat::Json => b"]\n".to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Encode a run of movies; `first` is whether it begins the export, which
+    /// decides whether a CSV header or a JSON separator is needed
+    pub fn encode(self, movies: &[model::Movie], first: bool) -> Result<Vec<u8>, DbError> {
+        let mut out = Vec::new();
+        match self {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(first)
+                    .from_writer(&mut out);
+                for movie in movies {
+                    writer.serialize(movie).map_err(encode_error)?;
+                }
+                writer.flush().map_err(encode_error)?;
+            }
+            ExportFormat::Json => {
+                for (index, movie) in movies.iter().enumerate() {
+                    if index > 0 || !first {
+                        out.push(b',');
+                    }
+                    serde_json::to_writer(&mut out, movie).map_err(encode_error)?;
+                }
+            }
+            ExportFormat::Ndjson => {
+                for movie in movies {
+                    serde_json::to_writer(&mut out, movie).map_err(encode_error)?;
+                    out.push(b'\n');
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn encode_error<E: fmt::Display>(e: E) -> DbError {
+    DbError::Database(format!("Unable to encode movie: {}", e))
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            _ => Err(format!(
+                "unknown export format `{}`, expected csv, json or ndjson",
+                value
+            )),
+        }
+    }
+}
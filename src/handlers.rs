@@ -1,77 +1,1223 @@
 use actix::prelude::*;
-use actix_web::{AsyncResponder, Json, FutureResponse, HttpResponse, Query, State};
-use futures::future::Future;
+use actix_web::dev::{JsonConfig, PathConfig, QueryConfig};
+use actix_web::http::{header, Cookie, CookieBuilder};
+use actix_web::multipart::MultipartItem;
+use actix_web::{
+    AsyncResponder, Error, FutureResponse, HttpMessage, HttpRequest, HttpResponse, Json, Path,
+    Query, ResponseError, State,
+};
+use bytes::Bytes;
+use cookie::SameSite;
+use futures::future::{self, Future};
+use futures::{stream, Stream};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fmt::Display;
+use std::rc::Rc;
 
-use crate::db::{CreateMovie, DbExecutor, DeleteMovie, GetAllMovies, GetMovie, UpdateMovie};
+use crate::auth::{current_user, CurrentLibrary, SESSION_COOKIE};
+use crate::cli::{BackupDirOpt, SessionOpt};
+use crate::db::audit::GetAudit;
+use crate::db::backup::{BackupDatabase, ListBackups};
+use crate::db::export::{ExportMovies, EXPORT_CHUNK_SIZE};
+use crate::db::import::{ImportMovies, Row};
+use crate::db::libraries::{AddMember, ListLibraries, ListMembers, RemoveMember};
+use crate::db::loans::{CheckIn, GetLoans, GetMovieLoans};
+use crate::db::revisions::{GetRevisions, RevertMovie};
+use crate::db::people::{
+    CastEntry, CastSet, GetCast, GetPeople, GetPerson, MergePeople, SetCast,
+};
+use crate::db::tokens::{ListTokens, RevokeToken};
+use crate::db::trash::{GetTrash, RestoreMovie};
+use crate::db::users::{DeleteUser, ListUsers, LoggedIn, Login, Logout};
+use crate::db::{
+    model, Created, DbError, DbExecutor, DeleteMovie, GetAllMovies, GetMovie, ListMovies,
+    Format, MovieFilter, Precondition, SearchMovies,
+};
+use crate::export::ExportFormat;
+use crate::import::{read_csv, Mapping};
+use crate::validation::{
+    Columns, LibraryForm, LoanForm, MovieForm, MoviePatchForm, TokenForm, UserForm, UserPatchForm,
+};
 
 pub struct AppState {
     pub db: Addr<DbExecutor>,
+    pub sessions: SessionOpt,
+    pub backups: BackupDirOpt,
+    /// Bytes the import endpoint reads before giving up
+    pub max_import_size: usize,
+    /// Shelf columns new movies may be placed in
+    pub columns: Columns,
+}
+
+/// Report malformed request bodies with the same JSON shape as `DbError`
+pub fn json_config(cfg: &mut JsonConfig<AppState>) {
+    cfg.error_handler(|e, _| bad_request(e));
+}
+
+/// Report malformed query strings with the same JSON shape as `DbError`
+pub fn query_config(cfg: &mut QueryConfig<AppState>) {
+    cfg.error_handler(|e, _| bad_request(e));
+}
+
+/// Report malformed path segments with the same JSON shape as `DbError`
+pub fn path_config(cfg: &mut PathConfig<AppState>) {
+    cfg.error_handler(|e, _| bad_request(e));
+}
+
+fn bad_request<E: Display>(e: E) -> Error {
+    DbError::validation(e.to_string()).into()
+}
+
+/// Who is making a request, as recorded in the audit log
+fn actor(req: &HttpRequest<AppState>) -> String {
+    match current_user(req) {
+        Some(user) => user.username,
+        None => req
+            .connection_info()
+            .remote()
+            .unwrap_or("unknown")
+            .to_string(),
+    }
+}
+
+/// Strong entity tag for a movie version
+fn etag(movie: &model::Movie) -> String {
+    format!("\"{}\"", movie.version)
+}
+
+/// The `If-Match` precondition a write must satisfy.
+///
+/// Writes without one are refused so that a stale client cannot overwrite
+/// changes it never saw. Weak or foreign tags can never match.
+fn precondition(req: &HttpRequest<AppState>) -> Result<Precondition, DbError> {
+    let value = req
+        .headers()
+        .get(header::IF_MATCH)
+        .ok_or_else(|| {
+            DbError::PreconditionRequired(
+                "An If-Match header with the movie's ETag is required".to_string(),
+            )
+        })?
+        .to_str()
+        .map_err(|_| DbError::validation("If-Match is not valid text"))?;
+    if value.trim() == "*" {
+        return Ok(Precondition::Any);
+    }
+    let versions = value
+        .split(',')
+        .filter_map(|tag| {
+            let tag = tag.trim();
+            if tag.len() < 2 || !tag.starts_with('"') || !tag.ends_with('"') {
+                return None;
+            }
+            tag[1..tag.len() - 1].parse().ok()
+        })
+        .collect();
+    Ok(Precondition::Versions(versions))
 }
 
 pub fn create_movie(
-    (create_movie, state): (Json<CreateMovie>, State<AppState>),
+    (req, library, form, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Json<MovieForm>,
+        State<AppState>,
+    ),
 ) -> FutureResponse<HttpResponse> {
+    let create_movie = match form
+        .into_inner()
+        .into_create(library.id(), actor(&req), &state.columns)
+    {
+        Ok(create_movie) => create_movie,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
     state
         .db
-        .send(create_movie.into_inner())
+        .send(create_movie)
         .from_err()
-        .and_then(|res| match res {
-            Ok(_) => Ok(HttpResponse::Ok().finish()),
-            Err(_) => Ok(HttpResponse::InternalServerError().into()),
+        .and_then(move |res| match res {
+            Ok(Created { movie, created }) => {
+                let mut response = if created {
+                    HttpResponse::Created()
+                } else {
+                    HttpResponse::Ok()
+                };
+                Ok(response
+                    .header(header::LOCATION, format!("{}?id={}", req.path(), movie.id))
+                    .header(header::ETAG, etag(&movie))
+                    .json(movie))
+            }
+            Err(e) => Ok(e.error_response()),
         })
         .responder()
 }
 
 pub fn delete_movie(
-    (delete_movie, state): (Query<DeleteMovie>, State<AppState>),
+    (req, library, movie, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Query<GetMovie>,
+        State<AppState>,
+    ),
 ) -> FutureResponse<HttpResponse> {
+    let precondition = match precondition(&req) {
+        Ok(precondition) => precondition,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
     state
         .db
-        .send(delete_movie.into_inner())
+        .send(DeleteMovie {
+            library: library.id(),
+            id: movie.into_inner().id,
+            precondition,
+            actor: actor(&req),
+        })
         .from_err()
         .and_then(|res| match res {
             Ok(_) => Ok(HttpResponse::Ok().finish()),
-            Err(_) => Ok(HttpResponse::InternalServerError().into()),
+            Err(e) => Ok(e.error_response()),
         })
         .responder()
 }
 
 pub fn get_movie(
-    (get_movie, state): (Query<GetMovie>, State<AppState>),
+    (library, get_movie, state): (CurrentLibrary, Query<GetMovie>, State<AppState>),
 ) -> FutureResponse<HttpResponse> {
     state
         .db
-        .send(get_movie.into_inner())
+        .send(GetMovie {
+            library: library.id(),
+            ..get_movie.into_inner()
+        })
         .from_err()
         .and_then(|res| match res {
-            Ok(movie) => Ok(HttpResponse::Ok().json(movie)),
-            Err(_) => Ok(HttpResponse::InternalServerError().into()),
+            Ok(movie) => Ok(HttpResponse::Ok()
+                .header(header::ETAG, etag(&movie))
+                .json(movie)),
+            Err(e) => Ok(e.error_response()),
         })
         .responder()
 }
 
 pub fn update_movie(
-    (update_movie, state): (Json<UpdateMovie>, State<AppState>),
+    (req, library, form, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Json<MovieForm>,
+        State<AppState>,
+    ),
 ) -> FutureResponse<HttpResponse> {
+    let update_movie = match precondition(&req)
+        .and_then(|p| {
+            form.into_inner()
+                .into_update(library.id(), p, actor(&req), &state.columns)
+        })
+    {
+        Ok(update_movie) => update_movie,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
     state
         .db
-        .send(update_movie.into_inner())
+        .send(update_movie)
         .from_err()
         .and_then(|res| match res {
-            Ok(_) => Ok(HttpResponse::Ok().finish()),
-            Err(_) => Ok(HttpResponse::InternalServerError().into()),
+            Ok(movie) => Ok(HttpResponse::Ok()
+                .header(header::ETAG, etag(&movie))
+                .json(movie)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn patch_movie(
+    (req, library, movie, form, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Query<GetMovie>,
+        Json<MoviePatchForm>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let patch_movie = match precondition(&req).and_then(|p| {
+        let id = movie.into_inner().id;
+        form.into_inner()
+            .into_patch(library.id(), id, p, actor(&req), &state.columns)
+    }) {
+        Ok(patch_movie) => patch_movie,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(patch_movie)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(movie) => Ok(HttpResponse::Ok()
+                .header(header::ETAG, etag(&movie))
+                .json(movie)),
+            Err(e) => Ok(e.error_response()),
         })
         .responder()
 }
 
-pub fn get_all_movies(state: State<AppState>) -> FutureResponse<HttpResponse> {
+pub fn get_all_movies(
+    (library, get_all_movies, state): (CurrentLibrary, Query<GetAllMovies>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
     state
         .db
-        .send(GetAllMovies {})
+        .send(GetAllMovies {
+            library: library.id(),
+            ..get_all_movies.into_inner()
+        })
         .from_err()
         .and_then(|res| match res {
             Ok(all_movies) => Ok(HttpResponse::Ok().json(all_movies)),
-            Err(_) => Ok(HttpResponse::InternalServerError().into()),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn get_trash(
+    (library, get_trash, state): (CurrentLibrary, Query<GetTrash>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetTrash {
+            library: library.id(),
+            ..get_trash.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(trash) => Ok(HttpResponse::Ok().json(trash)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn restore_movie(
+    (req, library, movie, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Query<GetMovie>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(RestoreMovie {
+            library: library.id(),
+            id: movie.into_inner().id,
+            actor: actor(&req),
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(movie) => Ok(HttpResponse::Ok()
+                .header(header::ETAG, etag(&movie))
+                .json(movie)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Response envelope for paginated listings
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+/// Link to the same resource with a different query string
+fn link_to(req: &HttpRequest<AppState>, query: &ListMovies) -> String {
+    let query = serde_urlencoded::to_string(query).unwrap_or_default();
+    format!("{}?{}", req.path(), query)
+}
+
+pub fn list_movies(
+    (req, library, list_movies, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Query<ListMovies>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(ListMovies {
+            library: library.id(),
+            ..list_movies.into_inner()
+        })
+        .from_err()
+        .and_then(move |res| match res {
+            Ok(page) => Ok(HttpResponse::Ok().json(Page::<model::Movie> {
+                next: page.next.map(|next| link_to(&req, &next)),
+                prev: page.prev.map(|prev| link_to(&req, &prev)),
+                items: page.items,
+                total: page.total,
+            })),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn search_movies(
+    (library, search_movies, state): (CurrentLibrary, Query<SearchMovies>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(SearchMovies {
+            library: library.id(),
+            ..search_movies.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(hits) => Ok(HttpResponse::Ok().json(hits)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn get_people(
+    (library, get_people, state): (CurrentLibrary, Query<GetPeople>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetPeople {
+            library: library.id(),
+            ..get_people.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(people) => Ok(HttpResponse::Ok().json(people)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn get_person(
+    (library, get_person, state): (CurrentLibrary, Query<GetPerson>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetPerson {
+            library: library.id(),
+            ..get_person.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(filmography) => Ok(HttpResponse::Ok().json(filmography)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// People to merge, as sent by a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeRequest {
+    pub into: String,
+    pub from: Vec<String>,
+}
+
+pub fn merge_people(
+    (req, library, merge, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Json<MergeRequest>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let MergeRequest { into, from } = merge.into_inner();
+    state
+        .db
+        .send(MergePeople {
+            library: library.id(),
+            into,
+            from,
+            actor: actor(&req),
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(person) => Ok(HttpResponse::Ok().json(person)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn get_cast(
+    (library, get_cast, state): (CurrentLibrary, Query<GetCast>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetCast {
+            library: library.id(),
+            ..get_cast.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(cast) => Ok(HttpResponse::Ok().json(cast)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// The new cast of a movie, in billing order
+type Cast = Json<Vec<CastEntry>>;
+
+pub fn set_cast(
+    (req, library, movie, cast, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Query<GetCast>,
+        Cast,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let precondition = match precondition(&req) {
+        Ok(precondition) => precondition,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(SetCast {
+            library: library.id(),
+            id: movie.into_inner().id,
+            cast: cast.into_inner(),
+            precondition,
+            actor: actor(&req),
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(CastSet { cast, movie }) => Ok(HttpResponse::Ok()
+                .header(header::ETAG, etag(&movie))
+                .json(cast)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn get_audit(
+    (library, get_audit, state): (CurrentLibrary, Query<GetAudit>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetAudit {
+            library: library.id(),
+            ..get_audit.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// The audit log of one movie, which stays available after it is purged
+pub fn movie_history(
+    (library, movie, get_audit, state): (
+        CurrentLibrary,
+        Path<GetMovie>,
+        Query<GetAudit>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetAudit {
+            library: library.id(),
+            movie: Some(movie.into_inner().id),
+            ..get_audit.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn get_revisions(
+    (library, get_revisions, state): (CurrentLibrary, Path<GetRevisions>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetRevisions {
+            library: library.id(),
+            ..get_revisions.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(revisions) => Ok(HttpResponse::Ok().json(revisions)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Path of a single revision
+#[derive(Debug, Deserialize)]
+pub struct RevisionPath {
+    pub id: String,
+    pub version: i32,
+}
+
+pub fn revert_movie(
+    (req, library, revision, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Path<RevisionPath>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let revision = revision.into_inner();
+    state
+        .db
+        .send(RevertMovie {
+            library: library.id(),
+            id: revision.id,
+            version: revision.version,
+            columns: state.columns.clone(),
+            actor: actor(&req),
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(movie) => Ok(HttpResponse::Ok()
+                .header(header::ETAG, etag(&movie))
+                .json(movie)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/*
+ * Lending movies out
+ */
+pub fn get_loans(
+    (library, get_loans, state): (CurrentLibrary, Query<GetLoans>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetLoans {
+            library: library.id(),
+            ..get_loans.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(loans) => Ok(HttpResponse::Ok().json(loans)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn get_movie_loans(
+    (library, get_loans, state): (CurrentLibrary, Path<GetMovieLoans>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(GetMovieLoans {
+            library: library.id(),
+            ..get_loans.into_inner()
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(loans) => Ok(HttpResponse::Ok().json(loans)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn check_out(
+    (req, library, movie, form, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Path<GetMovie>,
+        Json<LoanForm>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let check_out = match form
+        .into_inner()
+        .into_check_out(library.id(), movie.into_inner().id, actor(&req))
+    {
+        Ok(check_out) => check_out,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(check_out)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(loan) => Ok(HttpResponse::Created().json(loan)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn check_in(
+    (req, library, movie, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Path<GetMovie>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(CheckIn {
+            library: library.id(),
+            movie_id: movie.into_inner().id,
+            actor: actor(&req),
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(loan) => Ok(HttpResponse::Ok().json(loan)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportQuery {
+    pub dry_run: bool,
+}
+
+/// Import movies from the `file` part of a multipart upload.
+///
+/// Columns are matched to fields by header name; any number of `map` parts
+/// of the form `field=Header` override that. Either every row is imported or,
+/// if any row is invalid, none are and each bad row is reported by line.
+pub fn import_csv(
+    (req, library, query, state): (
+        HttpRequest<AppState>,
+        CurrentLibrary,
+        Query<ImportQuery>,
+        State<AppState>,
+    ),
+) -> FutureResponse<HttpResponse> {
+    let db = state.db.clone();
+    let dry_run = query.dry_run;
+    let library = library.id();
+    let actor = actor(&req);
+    let max_size = state.max_import_size;
+    let columns = state.columns.clone();
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > max_size) {
+        return Box::new(future::ok(upload_too_large(max_size).error_response()));
+    }
+    // Shared by every part, so that many small parts count as much as one
+    // large one
+    let received = Rc::new(Cell::new(0));
+    req.multipart()
+        .map_err(bad_request)
+        .filter_map(|item| match item {
+            MultipartItem::Field(field) => Some(field),
+            MultipartItem::Nested(_) => None,
+        })
+        .and_then(move |field| {
+            let name = field
+                .content_disposition()
+                .and_then(|disposition| disposition.get_name().map(str::to_string))
+                .unwrap_or_default();
+            if !IMPORT_PARTS.contains(&name.as_str()) {
+                let e = bad_request(format!(
+                    "Unknown part `{}`, expected {}",
+                    name,
+                    IMPORT_PARTS.join(" or ")
+                ));
+                return future::Either::A(future::err(e));
+            }
+            let received = received.clone();
+            future::Either::B(
+                field
+                    .map_err(bad_request)
+                    .fold(Vec::new(), move |mut body, chunk: Bytes| {
+                        received.set(received.get() + chunk.len());
+                        if received.get() > max_size {
+                            return Err(Error::from(upload_too_large(max_size)));
+                        }
+                        body.extend_from_slice(&chunk);
+                        Ok(body)
+                    })
+                    .map(move |body| (name, body)),
+            )
+        })
+        .collect()
+        .and_then(move |parts| {
+            let rows = csv_rows(parts, &library, &actor, &columns);
+            match rows {
+                Ok(rows) => future::Either::A(
+                    db.send(ImportMovies { rows, dry_run })
+                        .from_err()
+                        .and_then(|res| match res {
+                            Ok(report) => Ok(HttpResponse::Ok().json(report)),
+                            Err(e) => Ok(e.error_response()),
+                        }),
+                ),
+                Err(e) => future::Either::B(future::ok(e.error_response())),
+            }
+        })
+        .responder()
+}
+
+fn upload_too_large(max_size: usize) -> DbError {
+    DbError::PayloadTooLarge(format!(
+        "The upload is larger than the {} bytes allowed",
+        max_size
+    ))
+}
+
+/// The multipart parts the import endpoint reads
+const IMPORT_PARTS: &[&str] = &["file", "map"];
+
+fn csv_rows(
+    parts: Vec<(String, Vec<u8>)>,
+    library: &str,
+    actor: &str,
+    columns: &Columns,
+) -> Result<Vec<Row>, DbError> {
+    let mut file = None;
+    let mut pairs = Vec::new();
+    for (name, body) in parts {
+        match name.as_str() {
+            "file" => file = Some(body),
+            "map" => pairs.push(
+                String::from_utf8(body)
+                    .map_err(|_| DbError::validation("map must be text"))?,
+            ),
+            _ => {}
+        }
+    }
+    let file = file.ok_or_else(|| DbError::validation("A `file` part with the CSV is required"))?;
+    let mapping = Mapping::parse(&pairs)?;
+    read_csv(file.as_slice(), &mapping, library, actor, columns)
+}
+
+/// Filters for an export, the same as for `/movies`, along with the file
+/// `format` to write.
+///
+/// Here `format` is the file format, so the `/movies` filter on a movie's
+/// format is spelled `media_format` instead, as in
+/// `?format=csv&media_format=DVD`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_format: Option<Format>,
+    #[serde(flatten)]
+    pub filter: MovieFilter,
+}
+
+/// Every movie matching the filters, written out as it is read from the
+/// database a chunk at a time rather than collected first
+pub fn export_movies(
+    (library, query, state): (CurrentLibrary, Query<ExportQuery>, State<AppState>),
+) -> HttpResponse {
+    let ExportQuery {
+        format,
+        media_format,
+        filter,
+    } = query.into_inner();
+    let filter = MovieFilter {
+        format: media_format,
+        ..filter
+    };
+    let library = library.id();
+    let db = state.db.clone();
+
+    // Each chunk starts after the last movie of the one before
+    let chunks = stream::unfold(Some(None), move |after: Option<Option<model::Movie>>| {
+        let after = after?;
+        let first = after.is_none();
+        let chunk = db
+            .send(ExportMovies {
+                library: library.clone(),
+                filter: filter.clone(),
+                after,
+            })
+            .from_err::<Error>()
+            .and_then(move |res| {
+                let movies = res?;
+                let bytes = format.encode(&movies, first)?;
+                let next = if movies.len() as i64 == EXPORT_CHUNK_SIZE {
+                    movies.last().cloned().map(Some)
+                } else {
+                    None
+                };
+                Ok((Bytes::from(bytes), next))
+            });
+        Some(chunk)
+    });
+    let body = stream::once(Ok(Bytes::from(format.start())))
+        .chain(chunks)
+        .chain(stream::once(Ok(Bytes::from(format.end()))))
+        // An empty chunk would end the response early
+        .filter(|bytes| !bytes.is_empty());
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"movies.{}\"", format.extension()),
+        )
+        .streaming(body)
+}
+
+pub fn list_backups(state: State<AppState>) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(ListBackups {
+            dir: state.backups.dir.clone(),
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(backups) => Ok(HttpResponse::Ok().json(backups)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Snapshot the database into the backup directory, rotating out old ones
+pub fn create_backup(state: State<AppState>) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(BackupDatabase {
+            dir: state.backups.dir.clone(),
+            keep: state.backups.keep,
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(backup) => Ok(HttpResponse::Created().json(backup)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+fn session_cookie(sessions: &SessionOpt, value: String) -> CookieBuilder {
+    Cookie::build(SESSION_COOKIE, value)
+        .path("/")
+        .http_only(true)
+        .secure(sessions.secure)
+        .same_site(SameSite::Strict)
+}
+
+/// Start a session, handing its token back in an HttpOnly cookie
+pub fn login(
+    (credentials, state): (Json<Credentials>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let Credentials { username, password } = credentials.into_inner();
+    let sessions = state.sessions.clone();
+    let ttl = sessions.ttl;
+    let cookie = move |token| {
+        session_cookie(&sessions, token)
+            .max_age(time::Duration::seconds(ttl.num_seconds()))
+            .finish()
+    };
+    state
+        .db
+        .send(Login {
+            username,
+            password,
+            ttl,
+        })
+        .from_err()
+        .and_then(move |res| match res {
+            Ok(LoggedIn { user, token, .. }) => {
+                Ok(HttpResponse::Ok().cookie(cookie(token)).json(user))
+            }
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// End the current session and clear its cookie
+pub fn logout(
+    (req, state): (HttpRequest<AppState>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let token = req
+        .cookie(SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .unwrap_or_default();
+    let expired = session_cookie(&state.sessions, String::new())
+        .max_age(time::Duration::seconds(0))
+        .finish();
+    state
+        .db
+        .send(Logout { token })
+        .from_err()
+        .and_then(move |res| match res {
+            Ok(()) => Ok(HttpResponse::NoContent().cookie(expired).finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+fn logged_in(req: &HttpRequest<AppState>) -> Result<model::User, DbError> {
+    current_user(req).ok_or_else(|| DbError::Unauthorized("Log in to use the API".to_string()))
+}
+
+/// The logged in user
+pub fn whoami(req: HttpRequest<AppState>) -> HttpResponse {
+    match logged_in(&req) {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(e) => e.error_response(),
+    }
+}
+
+/*
+ * The logged in user's API tokens
+ */
+pub fn list_tokens(
+    (req, state): (HttpRequest<AppState>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let user = match logged_in(&req) {
+        Ok(user) => user,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(ListTokens { user_id: user.id })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(tokens) => Ok(HttpResponse::Ok().json(tokens)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Issue a token, which the response shows for the only time
+pub fn create_token(
+    (req, token, state): (HttpRequest<AppState>, Json<TokenForm>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let token = match logged_in(&req).and_then(|user| token.into_inner().into_create(user.id)) {
+        Ok(token) => token,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(token)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(token) => Ok(HttpResponse::Created().json(token)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Path of a single API token
+#[derive(Debug, Deserialize)]
+pub struct TokenPath {
+    pub id: String,
+}
+
+pub fn revoke_token(
+    (req, token, state): (HttpRequest<AppState>, Path<TokenPath>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let user = match logged_in(&req) {
+        Ok(user) => user,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(RevokeToken {
+            user_id: user.id,
+            id: token.into_inner().id,
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(()) => Ok(HttpResponse::NoContent().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/*
+ * User management, for admins
+ */
+pub fn list_users(state: State<AppState>) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(ListUsers)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(users) => Ok(HttpResponse::Ok().json(users)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn create_user(
+    (user, state): (Json<UserForm>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let user = match user.into_inner().into_create() {
+        Ok(user) => user,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(user)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(user) => Ok(HttpResponse::Created().json(user)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Path of a single user
+#[derive(Debug, Deserialize)]
+pub struct UserPath {
+    pub id: String,
+}
+
+pub fn update_user(
+    (user, changes, state): (Path<UserPath>, Json<UserPatchForm>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let update = match changes.into_inner().into_update(user.into_inner().id) {
+        Ok(update) => update,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(update)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(user) => Ok(HttpResponse::Ok().json(user)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn delete_user(
+    (user, state): (Path<UserPath>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(DeleteUser {
+            id: user.into_inner().id,
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/*
+ * Libraries
+ */
+/// The libraries the logged in user belongs to, or all of them for an admin
+pub fn list_libraries(
+    (req, state): (HttpRequest<AppState>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let user = match logged_in(&req) {
+        Ok(user) => user,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(ListLibraries { user })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(libraries) => Ok(HttpResponse::Ok().json(libraries)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Create a library with the admin creating it as its first member
+pub fn create_library(
+    (req, library, state): (HttpRequest<AppState>, Json<LibraryForm>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let library = match logged_in(&req).and_then(|user| library.into_inner().into_create(user.id))
+    {
+        Ok(library) => library,
+        Err(e) => return Box::new(future::ok(e.error_response())),
+    };
+    state
+        .db
+        .send(library)
+        .from_err()
+        .and_then(|res| match res {
+            Ok(library) => Ok(HttpResponse::Created().json(library)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Path of a library
+#[derive(Debug, Deserialize)]
+pub struct LibraryPath {
+    pub lib: String,
+}
+
+pub fn list_members(
+    (library, state): (Path<LibraryPath>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    state
+        .db
+        .send(ListMembers {
+            library: library.into_inner().lib,
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(users) => Ok(HttpResponse::Ok().json(users)),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+/// Path of one member of a library
+#[derive(Debug, Deserialize)]
+pub struct MemberPath {
+    pub lib: String,
+    pub user: String,
+}
+
+pub fn add_member(
+    (member, state): (Path<MemberPath>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let member = member.into_inner();
+    state
+        .db
+        .send(AddMember {
+            library: member.lib,
+            user_id: member.user,
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(()) => Ok(HttpResponse::NoContent().finish()),
+            Err(e) => Ok(e.error_response()),
+        })
+        .responder()
+}
+
+pub fn remove_member(
+    (member, state): (Path<MemberPath>, State<AppState>),
+) -> FutureResponse<HttpResponse> {
+    let member = member.into_inner();
+    state
+        .db
+        .send(RemoveMember {
+            library: member.lib,
+            user_id: member.user,
+        })
+        .from_err()
+        .and_then(|res| match res {
+            Ok(()) => Ok(HttpResponse::NoContent().finish()),
+            Err(e) => Ok(e.error_response()),
         })
         .responder()
 }
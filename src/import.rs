@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use crate::db::import::Row;
+use crate::db::DbError;
+use crate::validation::{Columns, MovieForm};
+
+/// The `CreateMovie` fields a CSV column can be mapped onto
+pub const FIELDS: &[&str] = &[
+    "id", "title", "rating", "category", "format", "aspect", "actors", "drawer", "column",
+];
+
+/// Which CSV header feeds each movie field.
+///
+/// Fields not named explicitly are matched to a header with the same name,
+/// ignoring case and punctuation, so `Title` and ` DRAWER_` need no mapping
+/// while `Name` for the title or `Aspect ratio` for the aspect do.
+#[derive(Debug, Clone, Default)]
+pub struct Mapping {
+    headers: BTreeMap<&'static str, String>,
+}
+
+impl Mapping {
+    /// Build a mapping from `field=Header` pairs
+    pub fn parse<S: AsRef<str>>(pairs: &[S]) -> Result<Self, DbError> {
+        let mut mapping = Mapping::default();
+        for pair in pairs {
+            let pair = pair.as_ref();
+            let (field, header) = match pair.find('=') {
+                Some(split) => (pair[..split].trim(), pair[split + 1..].trim()),
+                None => {
+                    return Err(DbError::validation(format!(
+                        "invalid mapping `{}`, expected field=Header",
+                        pair
+                    )))
+                }
+            };
+            mapping.set(field, header)?;
+        }
+        Ok(mapping)
+    }
+
+    pub fn set(&mut self, field: &str, header: &str) -> Result<(), DbError> {
+        let field = FIELDS
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(field))
+            .ok_or_else(|| {
+                DbError::validation(format!(
+                    "unknown field `{}`, expected one of {}",
+                    field,
+                    FIELDS.join(", ")
+                ))
+            })?;
+        self.headers.insert(field, header.to_string());
+        Ok(())
+    }
+
+    /// The column index of every mapped field present in `headers`
+    fn resolve(&self, headers: &csv::StringRecord) -> Result<Vec<(&'static str, usize)>, DbError> {
+        let mut columns = Vec::new();
+        for field in FIELDS {
+            let wanted = self.headers.get(field).map(String::as_str).unwrap_or(field);
+            match headers.iter().position(|header| same_name(header, wanted)) {
+                Some(index) => columns.push((*field, index)),
+                None if self.headers.contains_key(field) => {
+                    return Err(DbError::validation(format!(
+                        "no `{}` column for {}",
+                        wanted, field
+                    )))
+                }
+                None => {}
+            }
+        }
+        Ok(columns)
+    }
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    normalize(a) == normalize(b)
+}
+
+/// Read a CSV file with a header row into movies for `library` ready for
+/// `db::import::import`, validating each row on its own
+pub fn read_csv<R: Read>(
+    input: R,
+    mapping: &Mapping,
+    library: &str,
+    actor: &str,
+    columns: &Columns,
+) -> Result<Vec<Row>, DbError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(input);
+    let headers = reader
+        .headers()
+        .map_err(|e| DbError::validation(format!("Unable to read CSV header: {}", e)))?
+        .clone();
+    let fields = mapping.resolve(&headers)?;
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record =
+            record.map_err(|e| DbError::validation(format!("Unable to read CSV: {}", e)))?;
+        let line = record.position().map(|pos| pos.line()).unwrap_or(0);
+
+        let mut form = MovieForm::default();
+        for &(field, index) in &fields {
+            let value = record.get(index).unwrap_or_default().to_string();
+            match field {
+                "id" => form.id = value,
+                "title" => form.title = value,
+                "rating" => form.rating = value,
+                "category" => form.category = value,
+                "format" => form.format = value,
+                "aspect" => form.aspect = value,
+                "actors" => form.actors = value,
+                "drawer" => form.drawer = value,
+                "column" => form.column = value,
+                _ => unreachable!("{} is not in FIELDS", field),
+            }
+        }
+        rows.push(Row {
+            line,
+            movie: form.into_create(library.to_string(), actor.to_string(), columns),
+        });
+    }
+    if rows.is_empty() {
+        return Err(DbError::validation("The CSV file has no rows"));
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(csv: &str, pairs: &[&str]) -> Result<Vec<Row>, DbError> {
+        let mapping = Mapping::parse(pairs)?;
+        read_csv(csv.as_bytes(), &mapping, "home", "test", &Columns::default())
+    }
+
+    #[test]
+    fn headers_match_fields_ignoring_case_and_punctuation() {
+        let rows = read(
+            " TITLE ,Rating,format,ASPECT,Drawer_,column\nAlien,R,DVD,WS,3,Left\n",
+            &[],
+        )
+        .unwrap();
+        let movie = rows[0].movie.as_ref().unwrap();
+        assert_eq!(movie.title, "Alien");
+        assert_eq!(movie.drawer, "3");
+        assert_eq!(rows[0].line, 2);
+    }
+
+    #[test]
+    fn other_headers_need_a_mapping() {
+        let csv = "Name,Rating,format,Aspect ratio,drawer,column\nAlien,R,DVD,WS,3,Left\n";
+
+        let unmapped = read(csv, &[]).unwrap();
+        let e = unmapped[0].movie.as_ref().unwrap_err();
+        let details = e.details().unwrap();
+        assert!(details.get("title").is_some());
+        assert!(details.get("aspect").is_some());
+
+        let mapped = read(csv, &["title=name", "aspect = Aspect Ratio"]).unwrap();
+        assert_eq!(mapped[0].movie.as_ref().unwrap().title, "Alien");
+    }
+
+    #[test]
+    fn mappings_must_name_a_field_and_a_header_that_exists() {
+        let csv = "title,rating\nAlien,R\n";
+        assert!(read(csv, &["name"]).is_err());
+        assert!(read(csv, &["director=Director"]).is_err());
+        assert!(read(csv, &["title=Name"]).is_err());
+    }
+}
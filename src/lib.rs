@@ -1,6 +1,11 @@
 #[macro_use]
 extern crate diesel;
 
+pub mod auth;
+pub mod cli;
+pub mod export;
 pub mod handlers;
+pub mod import;
+pub mod validation;
 #[allow(proc_macro_derive_resolution_fallback)]
 pub mod db;
\ No newline at end of file
@@ -1,59 +1,510 @@
 use moviedb::{
     self,
+    auth::RequireLogin,
+    cli::{
+        BackupDirOpt, BackupOpt, Command, CreateAdminOpt, ExportOpt, ImportOpt, MigrateOpt, Opt,
+        PurgeOpt, RestoreOpt, ServeOpt,
+    },
     db,
-    db::DbExecutor,
+    db::{
+        backup, export, import, libraries, migrations, pool::ConnectionOptions, trash, users,
+        DbExecutor, MovieFilter, Role,
+    },
     handlers::{
-        create_movie, delete_movie, get_all_movies, get_movie, update_movie, AppState,
+        add_member, check_in, check_out, create_backup, create_library, create_movie,
+        create_token, create_user, delete_movie, delete_user, export_movies, get_all_movies,
+        get_audit, get_cast, get_loans, get_movie, get_movie_loans, get_people, get_person,
+        get_revisions, get_trash, import_csv, json_config, list_backups, list_libraries,
+        list_members, list_movies, list_tokens, list_users, login, logout, merge_people,
+        movie_history, patch_movie, path_config, query_config, remove_member, restore_movie,
+        revert_movie, revoke_token, search_movies, set_cast, update_movie, update_user, whoami,
+        AppState,
     },
+    import::{read_csv, Mapping},
+    validation::UserForm,
 };
 
-use actix;
 use actix::prelude::*;
 use actix_web::{fs, http, middleware, server, App};
+use chrono::Duration;
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
-use pretty_env_logger;
+use log::error;
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+use structopt::StructOpt;
 
 fn main() {
     pretty_env_logger::init_custom_env("MOVIEDB_LOG");
-    db::init_db("movies.db");
+    let opt = Opt::from_args();
+
+    match opt.cmd {
+        Command::Serve(opt) => serve(opt),
+        Command::Migrate(opt) => migrate(opt),
+        Command::Purge(opt) => purge(opt),
+        Command::Import(opt) => import_movies(opt),
+        Command::Export(opt) => export_to_file(opt),
+        Command::Backup(opt) => backup_now(opt),
+        Command::Restore(opt) => restore(opt),
+        Command::CreateAdmin(opt) => create_admin(opt),
+    }
+}
+
+fn create_admin(opt: CreateAdminOpt) {
+    let password = env::var("MOVIEDB_PASSWORD").unwrap_or_else(|_| {
+        eprint!("Password for {}: ", opt.username);
+        let mut password = String::new();
+        io::stdin()
+            .read_line(&mut password)
+            .expect("Unable to read the password");
+        password.trim_end_matches(&['\r', '\n'][..]).to_string()
+    });
+    let form = UserForm {
+        username: opt.username,
+        password,
+        role: Role::Admin.to_string(),
+    };
+    let user = form.into_create().unwrap_or_else(|e| {
+        eprintln!("{}", e.message());
+        let fields = e.details().and_then(|details| details.as_object());
+        for (field, messages) in fields.into_iter().flatten() {
+            for message in messages.as_array().into_iter().flatten() {
+                eprintln!("  {}: {}", field, message.as_str().unwrap_or(""));
+            }
+        }
+        process::exit(1);
+    });
+
+    db::init_db(&opt.db.database);
+    let conn = opt.db.establish();
+    match users::create_user(&conn, &user) {
+        Ok(user) => println!("Created {} ({})", user.username, user.id),
+        Err(e) => {
+            eprintln!("Unable to create {}: {}", user.username, e.message());
+            process::exit(1);
+        }
+    }
+}
+
+fn backup_now(opt: BackupOpt) {
+    db::init_db(&opt.db.database);
+    let conn = opt.db.establish();
+
+    match backup::snapshot(&conn, &opt.backups.dir, opt.backups.keep) {
+        Ok(info) => println!(
+            "Backed up to {} ({} bytes)",
+            opt.backups.dir.join(&info.file).display(),
+            info.size
+        ),
+        Err(e) => {
+            eprintln!("Backup failed: {}", e.message());
+            process::exit(1);
+        }
+    }
+}
+
+fn restore(opt: RestoreOpt) {
+    let restored =
+        backup::restore(&opt.file, Path::new(&opt.db.database)).unwrap_or_else(|e| {
+            eprintln!("Restore failed: {}", e.message());
+            process::exit(1);
+        });
+
+    println!(
+        "Restored {} at schema version {}",
+        opt.file.display(),
+        restored.version
+    );
+    for step in &restored.migrated {
+        println!("Applied {}", step);
+    }
+    if let Some(previous) = restored.previous {
+        println!("The replaced database was saved as {}", previous.display());
+    }
+}
+
+/// Back up every `every` on a thread of its own for as long as the server runs
+fn schedule_backups(
+    db: ConnectionOptions,
+    database: String,
+    backups: BackupDirOpt,
+    every: Duration,
+) {
+    // Zero would snapshot in a tight loop and rotate every real backup away
+    let interval = match every.to_std() {
+        Ok(interval) if every > Duration::zero() => interval,
+        _ => {
+            eprintln!("The backup interval must be positive");
+            process::exit(1);
+        }
+    };
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let result = db
+            .establish(&database)
+            .map_err(|e| e.to_string())
+            .and_then(|conn| {
+                backup::snapshot(&conn, &backups.dir, backups.keep).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            error!("Scheduled backup failed: {}", e);
+        }
+    });
+}
+
+fn check_library(conn: &SqliteConnection, library: &str) {
+    if let Err(e) = libraries::find_library(conn, library) {
+        eprintln!("{}", e.message());
+        process::exit(1);
+    }
+}
+
+fn export_to_file(opt: ExportOpt) {
+    db::init_db(&opt.db.database);
+    let conn = opt.db.establish();
+    check_library(&conn, &opt.library);
+    let filter = serde_urlencoded::from_str::<MovieFilter>(&opt.filter).unwrap_or_else(|e| {
+        eprintln!("Invalid filter: {}", e);
+        process::exit(1);
+    });
+    let format = opt.format.unwrap_or_else(|| {
+        let extension = opt.output.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        extension.parse().unwrap_or_default()
+    });
+
+    let fail = |e: &dyn Display| -> ! {
+        eprintln!("Export to {} failed: {}", opt.output.display(), e);
+        process::exit(1);
+    };
+    let file = File::create(&opt.output).unwrap_or_else(|e| fail(&e));
+    let mut out = BufWriter::new(file);
+    out.write_all(&format.start()).unwrap_or_else(|e| fail(&e));
+    let mut count = 0;
+    let mut after = None;
+    loop {
+        let movies = export::chunk(&conn, &opt.library, &filter, after.as_ref())
+            .unwrap_or_else(|e| fail(&e));
+        let bytes = format.encode(&movies, after.is_none()).unwrap_or_else(|e| fail(&e));
+        out.write_all(&bytes).unwrap_or_else(|e| fail(&e));
+        count += movies.len();
+        if (movies.len() as i64) < export::EXPORT_CHUNK_SIZE {
+            break;
+        }
+        after = movies.last().cloned();
+    }
+    out.write_all(&format.end())
+        .and_then(|_| out.flush())
+        .unwrap_or_else(|e| fail(&e));
+
+    println!("Exported {} movie(s) to {}", count, opt.output.display());
+}
+
+fn import_movies(opt: ImportOpt) {
+    db::init_db(&opt.db.database);
+    let conn = opt.db.establish();
+    check_library(&conn, &opt.library);
+    let file = File::open(&opt.file).unwrap_or_else(|e| {
+        eprintln!("Unable to open {}: {}", opt.file.display(), e);
+        process::exit(1);
+    });
+
+    let report = Mapping::parse(&opt.map)
+        .and_then(|mapping| read_csv(file, &mapping, &opt.library, "import", &opt.columns))
+        .and_then(|rows| import::import(&conn, rows, opt.dry_run))
+        .unwrap_or_else(|e| {
+            eprintln!("Import failed: {}", e.message());
+            let rows = e.details().and_then(|details| details["rows"].as_array());
+            for row in rows.into_iter().flatten() {
+                eprintln!("  line {}: {}", row["line"], row["message"].as_str().unwrap_or(""));
+                if let Some(fields) = row["details"].as_object() {
+                    for (field, messages) in fields {
+                        for message in messages.as_array().into_iter().flatten() {
+                            eprintln!("    {}: {}", field, message.as_str().unwrap_or(""));
+                        }
+                    }
+                }
+            }
+            process::exit(1);
+        });
+
+    let verb = if opt.dry_run { "Would import" } else { "Imported" };
+    println!(
+        "{} {} row(s): {} new, {} already present",
+        verb, report.rows, report.created, report.unchanged
+    );
+}
+
+fn purge(opt: PurgeOpt) {
+    db::init_db(&opt.db.database);
+    let conn = opt.db.establish();
+    let cutoff = db::now()
+        .checked_sub_signed(opt.older_than)
+        .unwrap_or_else(|| {
+            eprintln!("--older-than reaches back past the earliest date that can be stored");
+            process::exit(1);
+        });
+
+    let result = if opt.dry_run {
+        trash::expired(&conn, cutoff)
+    } else {
+        trash::purge(&conn, cutoff, "purge")
+    };
+    let movies = result.unwrap_or_else(|e| {
+        eprintln!("Purge failed: {}", e);
+        process::exit(1);
+    });
+
+    let verb = if opt.dry_run { "Would purge" } else { "Purged" };
+    for movie in &movies {
+        println!("{} {} ({})", verb, movie.title, movie.id);
+    }
+    println!("{} {} movie(s) trashed before {}", verb, movies.len(), cutoff);
+}
+
+fn migrate(opt: MigrateOpt) {
+    let conn = SqliteConnection::establish(&opt.db.database)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", opt.db.database));
+    let current = migrations::current_version(&conn).expect("Unable to read schema version");
+    println!("Current schema version: {}", current);
+
+    let steps = migrations::plan(&conn, opt.target).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    if steps.is_empty() {
+        println!("Nothing to do");
+        return;
+    }
+
+    if opt.dry_run {
+        for step in &steps {
+            println!("-- {}\n{}", step, step.sql().trim_end());
+        }
+        return;
+    }
+
+    if let Err(e) = migrations::apply(&conn, &steps) {
+        eprintln!("Migration failed: {}", e);
+        process::exit(1);
+    }
+    for step in &steps {
+        println!("Applied {}", step);
+    }
+}
+
+fn serve(opt: ServeOpt) {
+    db::init_db(&opt.db.database);
 
     let sys = actix::System::new("movie-db");
 
-    let manager = ConnectionManager::<SqliteConnection>::new("movies.db");
-    let pool = Pool::builder()
-        .build(manager)
+    let options = opt.db.connection_options();
+    let pool = options
+        .pool(&opt.db.database, opt.pool_size)
         .expect("Failed to create pool.");
 
-    let addr = SyncArbiter::start(3, move || DbExecutor(pool.clone()));
+    let addr = SyncArbiter::start(opt.workers, move || DbExecutor(pool.clone()));
+
+    if let Some(every) = opt.backup_every {
+        schedule_backups(options, opt.db.database.clone(), opt.backups.clone(), every);
+    }
+    let sessions = opt.sessions.clone();
+    let backups = opt.backups.clone();
+    let max_import_size = opt.max_import_size;
+    let columns = opt.columns.clone();
+
+    let static_dir = opt.static_dir.clone();
 
     // Start http server
     server::new(move || {
         vec![
-            App::with_state(AppState { db: addr.clone() })
+            App::with_state(AppState {
+                db: addr.clone(),
+                sessions: sessions.clone(),
+                backups: backups.clone(),
+                max_import_size,
+                columns: columns.clone(),
+            })
                 .prefix("/api")
                 .middleware(middleware::Logger::default())
-                .resource("/movie", |r| {
-                    r.method(http::Method::POST).with(create_movie);
-                    r.method(http::Method::DELETE).with(delete_movie);
-                    r.method(http::Method::GET).with(get_movie);
-                    r.method(http::Method::PUT).with(update_movie);
+                .middleware(RequireLogin)
+                .resource("/login", |r| {
+                    r.method(http::Method::POST)
+                        .with_config(login, |((cfg, _),)| json_config(cfg));
+                })
+                .resource("/logout", |r| r.method(http::Method::POST).with(logout))
+                .resource("/me", |r| r.method(http::Method::GET).with(whoami))
+                .resource("/tokens", |r| {
+                    r.method(http::Method::GET).with(list_tokens);
+                    r.method(http::Method::POST)
+                        .with_config(create_token, |((_, cfg, _),)| json_config(cfg));
+                })
+                .resource("/tokens/{id}", |r| {
+                    r.method(http::Method::DELETE)
+                        .with_config(revoke_token, |((_, cfg, _),)| path_config(cfg));
+                })
+                .resource("/libraries", |r| r.method(http::Method::GET).with(list_libraries))
+                .resource("/libraries/{lib}/movie", |r| {
+                    r.method(http::Method::POST)
+                        .with_config(create_movie, |((_, _, cfg, _),)| json_config(cfg));
+                    r.method(http::Method::DELETE)
+                        .with_config(delete_movie, |((_, _, cfg, _),)| query_config(cfg));
+                    r.method(http::Method::GET)
+                        .with_config(get_movie, |((_, cfg, _),)| query_config(cfg));
+                    r.method(http::Method::PUT)
+                        .with_config(update_movie, |((_, _, cfg, _),)| json_config(cfg));
+                    r.method(http::Method::PATCH)
+                        .with_config(patch_movie, |((_, _, query, json, _),)| {
+                            query_config(query);
+                            json_config(json);
+                        });
+                })
+                .resource("/libraries/{lib}/movie/cast", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_cast, |((_, cfg, _),)| query_config(cfg));
+                    r.method(http::Method::PUT)
+                        .with_config(set_cast, |((_, _, query, json, _),)| {
+                            query_config(query);
+                            json_config(json);
+                        });
+                })
+                .resource("/libraries/{lib}/movie/{id}/history", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(movie_history, |((_, path, query, _),)| {
+                            path_config(path);
+                            query_config(query);
+                        });
+                })
+                .resource("/libraries/{lib}/movie/{id}/revisions", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_revisions, |((_, cfg, _),)| path_config(cfg));
+                })
+                .resource("/libraries/{lib}/movie/{id}/revisions/{version}/restore", |r| {
+                    r.method(http::Method::POST)
+                        .with_config(revert_movie, |((_, _, cfg, _),)| path_config(cfg));
+                })
+                .resource("/libraries/{lib}/movie/{id}/loans", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_movie_loans, |((_, cfg, _),)| path_config(cfg));
+                    r.method(http::Method::POST)
+                        .with_config(check_out, |((_, _, path, json, _),)| {
+                            path_config(path);
+                            json_config(json);
+                        });
+                })
+                .resource("/libraries/{lib}/movie/{id}/loans/return", |r| {
+                    r.method(http::Method::POST)
+                        .with_config(check_in, |((_, _, cfg, _),)| path_config(cfg));
+                })
+                .resource("/libraries/{lib}/loans", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_loans, |((_, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/audit", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_audit, |((_, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/trash", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_trash, |((_, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/trash/restore", |r| {
+                    r.method(http::Method::POST)
+                        .with_config(restore_movie, |((_, _, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/export", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(export_movies, |((_, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/import/csv", |r| {
+                    r.method(http::Method::POST)
+                        .with_config(import_csv, |((_, _, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/movies", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(list_movies, |((_, _, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/search", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(search_movies, |((_, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/people", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_people, |((_, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/people/merge", |r| {
+                    r.method(http::Method::POST)
+                        .with_config(merge_people, |((_, _, cfg, _),)| json_config(cfg));
+                })
+                .resource("/libraries/{lib}/person", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_person, |((_, cfg, _),)| query_config(cfg));
                 })
                 .resource("/all_movies", |r| {
-                    r.method(http::Method::GET).with(get_all_movies)
+                    r.method(http::Method::GET)
+                        .with_config(get_all_movies, |((_, cfg, _),)| query_config(cfg));
+                })
+                .resource("/movies", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(list_movies, |((_, _, cfg, _),)| query_config(cfg));
+                })
+                .resource("/libraries/{lib}/all_movies", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(get_all_movies, |((_, cfg, _),)| query_config(cfg));
+                })
+                .resource("/admin/backups", |r| {
+                    r.method(http::Method::GET).with(list_backups);
+                    r.method(http::Method::POST).with(create_backup);
+                })
+                .resource("/admin/users", |r| {
+                    r.method(http::Method::GET).with(list_users);
+                    r.method(http::Method::POST)
+                        .with_config(create_user, |((cfg, _),)| json_config(cfg));
+                })
+                .resource("/admin/users/{id}", |r| {
+                    r.method(http::Method::PATCH)
+                        .with_config(update_user, |((path, json, _),)| {
+                            path_config(path);
+                            json_config(json);
+                        });
+                    r.method(http::Method::DELETE)
+                        .with_config(delete_user, |((cfg, _),)| path_config(cfg));
+                })
+                .resource("/admin/libraries", |r| {
+                    r.method(http::Method::POST)
+                        .with_config(create_library, |((_, cfg, _),)| json_config(cfg));
+                })
+                .resource("/admin/libraries/{lib}/members", |r| {
+                    r.method(http::Method::GET)
+                        .with_config(list_members, |((cfg, _),)| path_config(cfg));
+                })
+                .resource("/admin/libraries/{lib}/members/{user}", |r| {
+                    r.method(http::Method::PUT)
+                        .with_config(add_member, |((cfg, _),)| path_config(cfg));
+                    r.method(http::Method::DELETE)
+                        .with_config(remove_member, |((cfg, _),)| path_config(cfg));
                 }),
-            App::with_state(AppState { db: addr.clone() }).handler(
+            App::with_state(AppState {
+                db: addr.clone(),
+                sessions: sessions.clone(),
+                backups: backups.clone(),
+                max_import_size,
+                columns: columns.clone(),
+            }).handler(
                 "/",
-                fs::StaticFiles::new("./static")
-                    .unwrap()
+                fs::StaticFiles::new(&static_dir)
+                    .unwrap_or_else(|_| panic!("Unable to serve {}", static_dir.display()))
                     .index_file("./index.html"),
             ),
         ]
     })
-    .bind("127.0.0.1:8080")
-    .unwrap()
+    .bind(opt.bind_addr())
+    .unwrap_or_else(|_| panic!("Unable to bind to {}", opt.bind_addr()))
     .start();
 
-    println!("Started http server: http://127.0.0.1:8080");
+    println!("Started http server: http://{}", opt.bind_addr());
     let _ = sys.run();
 }
@@ -0,0 +1,739 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use chrono::{Duration, NaiveDate};
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::cli::parse_age;
+use crate::db::libraries::CreateLibrary;
+use crate::db::loans::CheckOut;
+use crate::db::model::MovieChanges;
+use crate::db::tokens::CreateToken;
+use crate::db::users::{CreateUser, UpdateUser};
+use crate::db::{
+    now, people, Aspect, CreateMovie, DbError, Format, PatchMovie, Precondition, Rating, Role,
+    UpdateMovie,
+};
+
+pub const MAX_ID_LEN: usize = 36;
+pub const MAX_TITLE_LEN: usize = 200;
+pub const MAX_CATEGORY_LEN: usize = 100;
+pub const MAX_ACTORS_LEN: usize = 2000;
+pub const MAX_NAME_LEN: usize = 100;
+pub const MAX_CONTACT_LEN: usize = 200;
+pub const MAX_DRAWER: u32 = 999;
+pub const MIN_PASSWORD_LEN: usize = 8;
+pub const MAX_PASSWORD_LEN: usize = 1024;
+/// The shelf columns a movie may be placed in unless `--columns` says
+/// otherwise
+pub const DEFAULT_COLUMNS: &str = "Left,Middle,Right";
+
+/// The shelf columns movies may be placed in, from a comma separated list
+#[derive(Debug, Clone)]
+pub struct Columns(Vec<String>);
+
+impl Columns {
+    /// The configured spelling of `value`, ignoring case, or why it is not
+    /// allowed
+    pub fn check(&self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        match self.0.iter().find(|column| column.eq_ignore_ascii_case(value)) {
+            Some(column) => Ok(column.clone()),
+            None if value.is_empty() => Err("Column is required".to_string()),
+            None => Err(format!("Column must be one of {}", self.0.join(", "))),
+        }
+    }
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        DEFAULT_COLUMNS.parse().expect("the default columns are valid")
+    }
+}
+
+impl FromStr for Columns {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut columns: Vec<String> = Vec::new();
+        for column in value.split(',').map(str::trim) {
+            if column.is_empty() {
+                return Err("column names must not be blank".to_string());
+            }
+            if columns.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+                return Err(format!("column `{}` is listed twice", column));
+            }
+            columns.push(column.to_string());
+        }
+        Ok(Columns(columns))
+    }
+}
+
+/// Collects every problem with a request so they can be reported together
+///
+/// Errors are keyed by field name, which lets a form show each message next
+/// to the input it belongs to.
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: BTreeMap<&'static str, Vec<String>>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn error<S: Into<String>>(&mut self, field: &'static str, message: S) {
+        self.errors.entry(field).or_default().push(message.into());
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The collected errors as a 400 response with a `details` object mapping
+    /// each field to its messages
+    pub fn into_error(self) -> DbError {
+        DbError::Validation {
+            message: format!("{} field(s) failed validation", self.errors.len()),
+            details: Some(json!(self.errors)),
+        }
+    }
+
+    pub fn finish(self) -> Result<(), DbError> {
+        if self.is_valid() {
+            Ok(())
+        } else {
+            Err(self.into_error())
+        }
+    }
+
+    /// Trimmed text that must not be blank or longer than `max` characters
+    pub fn required(&mut self, field: &'static str, value: &str, max: usize) -> String {
+        let value = value.trim();
+        if value.is_empty() {
+            self.error(field, format!("{} is required", capitalize(field)));
+        }
+        self.optional(field, value, max)
+    }
+
+    /// Trimmed text no longer than `max` characters
+    pub fn optional(&mut self, field: &'static str, value: &str, max: usize) -> String {
+        let value = value.trim();
+        if value.chars().count() > max {
+            self.error(
+                field,
+                format!("{} must be at most {} characters", capitalize(field), max),
+            );
+        }
+        value.to_string()
+    }
+
+    /// An optional UUID, normalized to lowercase hyphenated form
+    pub fn uuid(&mut self, field: &'static str, value: &str) -> Option<String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        match Uuid::parse_str(value) {
+            Ok(uuid) => Some(uuid.to_hyphenated().to_string()),
+            Err(_) => {
+                self.error(field, format!("{} must be a UUID", capitalize(field)));
+                None
+            }
+        }
+    }
+
+    /// One of a closed set of values such as a `Rating`
+    pub fn choice<T>(&mut self, field: &'static str, value: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: ToString,
+    {
+        if value.trim().is_empty() {
+            self.error(field, format!("{} is required", capitalize(field)));
+            return None;
+        }
+        match value.parse() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.error(field, capitalize(&e.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Comma separated names, each within `MAX_NAME_LEN`
+    pub fn actors(&mut self, value: &str) -> String {
+        let value = self.optional("actors", value, MAX_ACTORS_LEN);
+        if let Some(name) = people::split_names(&value)
+            .into_iter()
+            .find(|name| name.chars().count() > MAX_NAME_LEN)
+        {
+            self.error(
+                "actors",
+                format!(
+                    "Actor names must be at most {} characters, \"{}…\" is too long",
+                    MAX_NAME_LEN,
+                    name.chars().take(20).collect::<String>()
+                ),
+            );
+        }
+        value
+    }
+
+    /// A password of at least `MIN_PASSWORD_LEN` characters, kept exactly as
+    /// typed
+    pub fn password(&mut self, value: &str) -> String {
+        let len = value.chars().count();
+        if len < MIN_PASSWORD_LEN {
+            self.error(
+                "password",
+                format!("Password must be at least {} characters", MIN_PASSWORD_LEN),
+            );
+        } else if len > MAX_PASSWORD_LEN {
+            self.error(
+                "password",
+                format!("Password must be at most {} characters", MAX_PASSWORD_LEN),
+            );
+        }
+        value.to_string()
+    }
+
+    /// A positive length of time such as `90d`, in the units `--session-ttl`
+    /// takes
+    pub fn age(&mut self, field: &'static str, value: &str) -> Option<Duration> {
+        match parse_age(value) {
+            Ok(age) if age > Duration::zero() => Some(age),
+            Ok(_) => {
+                let label = capitalize(&field.replace('_', " "));
+                self.error(field, format!("{} must be more than zero", label));
+                None
+            }
+            Err(e) => {
+                self.error(field, capitalize(&e));
+                None
+            }
+        }
+    }
+
+    /// A `YYYY-MM-DD` date no earlier than today, UTC
+    pub fn future_date(&mut self, field: &'static str, value: &str) -> Option<NaiveDate> {
+        let value = value.trim();
+        let label = capitalize(&field.replace('_', " "));
+        match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) if date >= now().date() => Some(date),
+            Ok(_) => {
+                self.error(field, format!("{} must not be in the past", label));
+                None
+            }
+            Err(_) if value.is_empty() => {
+                self.error(field, format!("{} is required", label));
+                None
+            }
+            Err(_) => {
+                self.error(field, format!("{} must be a date such as 2024-01-31", label));
+                None
+            }
+        }
+    }
+
+    /// Drawer number from 1 to `MAX_DRAWER`, normalized without leading zeros
+    pub fn drawer(&mut self, value: &str) -> String {
+        let value = value.trim();
+        match value.parse::<u32>() {
+            Ok(drawer) if (1..=MAX_DRAWER).contains(&drawer) => drawer.to_string(),
+            _ if value.is_empty() => {
+                self.error("drawer", "Drawer is required");
+                String::new()
+            }
+            _ => {
+                self.error(
+                    "drawer",
+                    format!("Drawer must be a number from 1 to {}", MAX_DRAWER),
+                );
+                value.to_string()
+            }
+        }
+    }
+
+    /// One of `columns`, ignoring case
+    pub fn column(&mut self, value: &str, columns: &Columns) -> String {
+        columns.check(value).unwrap_or_else(|message| {
+            self.error("column", message);
+            value.trim().to_string()
+        })
+    }
+
+    /// One of `columns` like `column`, except that any other name is let
+    /// through along with why it would be rejected; a movie that is already
+    /// in a column no longer configured may stay there
+    pub fn kept_column(&mut self, value: &str, columns: &Columns) -> (String, Option<String>) {
+        match columns.check(value) {
+            Ok(column) => (column, None),
+            Err(_) if value.trim().is_empty() => (self.column(value, columns), None),
+            Err(message) => (value.trim().to_string(), Some(message)),
+        }
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// A movie as submitted by a client, before any checks.
+///
+/// Every field is plain text and defaults to empty so that a single request
+/// reports all missing or malformed fields at once rather than failing on
+/// the first one serde sees.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovieForm {
+    pub id: String,
+    pub title: String,
+    pub rating: String,
+    pub category: String,
+    pub format: String,
+    pub aspect: String,
+    pub actors: String,
+    pub drawer: String,
+    pub column: String,
+}
+
+impl MovieForm {
+    /// Check every movie field but the column, which the caller has already
+    /// checked, returning the cleaned values only if the enums parsed; the
+    /// caller still has to consult `v` for other errors
+    fn check(&self, v: &mut Validator, column: String) -> Option<CreateMovie> {
+        let title = v.required("title", &self.title, MAX_TITLE_LEN);
+        let rating = v.choice::<Rating>("rating", &self.rating);
+        let category = v.optional("category", &self.category, MAX_CATEGORY_LEN);
+        let format = v.choice::<Format>("format", &self.format);
+        let aspect = v.choice::<Aspect>("aspect", &self.aspect);
+        let actors = v.actors(&self.actors);
+        let drawer = v.drawer(&self.drawer);
+
+        Some(CreateMovie {
+            library: String::new(),
+            id: None,
+            title,
+            rating: rating?,
+            category,
+            format: format?,
+            aspect: aspect?,
+            actors,
+            drawer,
+            column,
+            actor: String::new(),
+        })
+    }
+
+    /// A new movie; `id` may be left empty to have one generated
+    pub fn into_create(
+        self,
+        library: String,
+        actor: String,
+        columns: &Columns,
+    ) -> Result<CreateMovie, DbError> {
+        let mut v = Validator::new();
+        let id = v.uuid("id", &self.id);
+        let column = v.column(&self.column, columns);
+        match self.check(&mut v, column) {
+            Some(movie) if v.is_valid() => Ok(CreateMovie {
+                library,
+                id,
+                actor,
+                ..movie
+            }),
+            _ => Err(v.into_error()),
+        }
+    }
+
+    pub fn into_update(
+        self,
+        library: String,
+        precondition: Precondition,
+        actor: String,
+        columns: &Columns,
+    ) -> Result<UpdateMovie, DbError> {
+        let mut v = Validator::new();
+        let id = v.required("id", &self.id, MAX_ID_LEN);
+        let (column, column_error) = v.kept_column(&self.column, columns);
+        match self.check(&mut v, column) {
+            Some(movie) if v.is_valid() => Ok(UpdateMovie {
+                library,
+                id,
+                title: movie.title,
+                rating: movie.rating,
+                category: movie.category,
+                format: movie.format,
+                aspect: movie.aspect,
+                actors: movie.actors,
+                drawer: movie.drawer,
+                column: movie.column,
+                column_error,
+                precondition,
+                actor,
+            }),
+            _ => Err(v.into_error()),
+        }
+    }
+}
+
+/// Some fields of a movie as submitted by a client, before any checks.
+///
+/// Absent and `null` fields are left unchanged; present ones are held to the
+/// same rules as `MovieForm`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MoviePatchForm {
+    pub title: Option<String>,
+    pub rating: Option<String>,
+    pub category: Option<String>,
+    pub format: Option<String>,
+    pub aspect: Option<String>,
+    pub actors: Option<String>,
+    pub drawer: Option<String>,
+    pub column: Option<String>,
+}
+
+impl MoviePatchForm {
+    pub fn into_patch(
+        self,
+        library: String,
+        id: String,
+        precondition: Precondition,
+        actor: String,
+        columns: &Columns,
+    ) -> Result<PatchMovie, DbError> {
+        let mut v = Validator::new();
+        let (column, column_error) = match self.column {
+            Some(column) => {
+                let (column, error) = v.kept_column(&column, columns);
+                (Some(column), error)
+            }
+            None => (None, None),
+        };
+        let changes = MovieChanges {
+            title: self
+                .title
+                .map(|title| v.required("title", &title, MAX_TITLE_LEN)),
+            rating: self.rating.and_then(|rating| v.choice("rating", &rating)),
+            category: self
+                .category
+                .map(|category| v.optional("category", &category, MAX_CATEGORY_LEN)),
+            format: self.format.and_then(|format| v.choice("format", &format)),
+            aspect: self.aspect.and_then(|aspect| v.choice("aspect", &aspect)),
+            actors: self.actors.map(|actors| v.actors(&actors)),
+            drawer: self.drawer.map(|drawer| v.drawer(&drawer)),
+            column,
+        };
+        v.finish()?;
+        Ok(PatchMovie {
+            library,
+            id,
+            changes,
+            column_error,
+            precondition,
+            actor,
+        })
+    }
+}
+
+/// A new account as submitted by an administrator
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UserForm {
+    pub username: String,
+    pub password: String,
+    /// Defaults to viewer
+    pub role: String,
+}
+
+impl UserForm {
+    pub fn into_create(self) -> Result<CreateUser, DbError> {
+        let mut v = Validator::new();
+        let username = v.required("username", &self.username, MAX_NAME_LEN);
+        if username.chars().any(char::is_whitespace) {
+            v.error("username", "Username must not contain spaces");
+        }
+        let password = v.password(&self.password);
+        let role = if self.role.trim().is_empty() {
+            Some(Role::Viewer)
+        } else {
+            v.choice("role", &self.role)
+        };
+        v.finish()?;
+        Ok(CreateUser {
+            username,
+            password,
+            role: role.unwrap_or(Role::Viewer),
+        })
+    }
+}
+
+/// Changes to an account; absent fields are left alone
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UserPatchForm {
+    pub role: Option<String>,
+    pub password: Option<String>,
+}
+
+impl UserPatchForm {
+    pub fn into_update(self, id: String) -> Result<UpdateUser, DbError> {
+        let mut v = Validator::new();
+        let role = self.role.and_then(|role| v.choice("role", &role));
+        let password = self.password.map(|password| v.password(&password));
+        v.finish()?;
+        Ok(UpdateUser { id, role, password })
+    }
+}
+
+/// A new API token as requested by its owner
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TokenForm {
+    pub name: String,
+    pub read_only: bool,
+    /// How long until it expires, such as `90d`; never when absent
+    pub expires_in: Option<String>,
+}
+
+impl TokenForm {
+    pub fn into_create(self, user_id: String) -> Result<CreateToken, DbError> {
+        let mut v = Validator::new();
+        let name = v.required("name", &self.name, MAX_NAME_LEN);
+        let expires_in = self
+            .expires_in
+            .and_then(|expires_in| v.age("expires_in", &expires_in));
+        let expires_at = expires_in.and_then(|age| {
+            let expires_at = now().checked_add_signed(age);
+            if expires_at.is_none() {
+                v.error("expires_in", "Expires in is too far in the future");
+            }
+            expires_at
+        });
+        v.finish()?;
+        Ok(CreateToken {
+            user_id,
+            name,
+            read_only: self.read_only,
+            expires_at,
+        })
+    }
+}
+
+/// A new library as requested by an admin
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LibraryForm {
+    pub name: String,
+}
+
+impl LibraryForm {
+    pub fn into_create(self, user_id: String) -> Result<CreateLibrary, DbError> {
+        let mut v = Validator::new();
+        let name = v.required("name", &self.name, MAX_NAME_LEN);
+        v.finish()?;
+        Ok(CreateLibrary { name, user_id })
+    }
+}
+
+/// A movie being lent out
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LoanForm {
+    pub borrower: String,
+    pub contact: String,
+    /// `YYYY-MM-DD`
+    pub due_date: String,
+}
+
+impl LoanForm {
+    pub fn into_check_out(
+        self,
+        library: String,
+        movie_id: String,
+        actor: String,
+    ) -> Result<CheckOut, DbError> {
+        let mut v = Validator::new();
+        let borrower = v.required("borrower", &self.borrower, MAX_NAME_LEN);
+        let contact = v.optional("contact", &self.contact, MAX_CONTACT_LEN);
+        match v.future_date("due_date", &self.due_date) {
+            Some(due_date) if v.is_valid() => Ok(CheckOut {
+                library,
+                movie_id,
+                borrower,
+                contact,
+                due_date,
+                actor,
+            }),
+            _ => Err(v.into_error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_movie() -> MovieForm {
+        MovieForm {
+            id: String::new(),
+            title: "Alien".to_string(),
+            rating: "R".to_string(),
+            category: "Horror".to_string(),
+            format: "Blu-ray".to_string(),
+            aspect: "Widescreen".to_string(),
+            actors: "Sigourney Weaver, Tom Skerritt".to_string(),
+            drawer: "3".to_string(),
+            column: "left".to_string(),
+        }
+    }
+
+    /// The fields that failed, with their messages
+    fn field_errors(e: DbError) -> BTreeMap<String, Vec<String>> {
+        let details = e.details().expect("validation errors have details").clone();
+        serde_json::from_value(details).unwrap()
+    }
+
+    #[test]
+    fn a_valid_movie_is_cleaned_up() {
+        let id = "0F8FAD5B-D9CB-469F-A165-70867728950E".to_string();
+        let form = MovieForm {
+            id,
+            title: "  Alien ".to_string(),
+            drawer: "003".to_string(),
+            ..valid_movie()
+        };
+        let movie = form
+            .into_create("home".to_string(), "test".to_string(), &Columns::default())
+            .unwrap();
+
+        assert_eq!(movie.id.as_deref(), Some("0f8fad5b-d9cb-469f-a165-70867728950e"));
+        assert_eq!(movie.title, "Alien");
+        assert_eq!(movie.rating, Rating::R);
+        assert_eq!(movie.format, Format::BluRay);
+        assert_eq!(movie.drawer, "3");
+        assert_eq!(movie.column, "Left");
+    }
+
+    #[test]
+    fn every_bad_field_is_reported_at_once() {
+        let form = MovieForm {
+            id: "not-a-uuid".to_string(),
+            title: " ".to_string(),
+            rating: "X".to_string(),
+            category: "c".repeat(MAX_CATEGORY_LEN + 1),
+            format: "Laserdisc".to_string(),
+            aspect: String::new(),
+            actors: "a".repeat(MAX_NAME_LEN + 1),
+            drawer: "0".to_string(),
+            column: "Attic".to_string(),
+        };
+        let e = form
+            .into_create("home".to_string(), "test".to_string(), &Columns::default())
+            .unwrap_err();
+
+        assert_eq!(e.message(), "9 field(s) failed validation");
+        let errors = field_errors(e);
+        let fields: Vec<&str> = errors.keys().map(String::as_str).collect();
+        assert_eq!(
+            fields,
+            [
+                "actors", "aspect", "category", "column", "drawer", "format", "id", "rating",
+                "title"
+            ]
+        );
+        assert_eq!(errors["title"], ["Title is required"]);
+        assert_eq!(errors["aspect"], ["Aspect is required"]);
+        assert_eq!(errors["column"], ["Column must be one of Left, Middle, Right"]);
+        assert_eq!(errors["drawer"], ["Drawer must be a number from 1 to 999"]);
+    }
+
+    #[test]
+    fn lengths_are_counted_in_characters() {
+        let mut v = Validator::new();
+        v.required("title", &"é".repeat(MAX_TITLE_LEN), MAX_TITLE_LEN);
+        assert!(v.is_valid());
+        v.required("title", &"é".repeat(MAX_TITLE_LEN + 1), MAX_TITLE_LEN);
+        assert!(!v.is_valid());
+    }
+
+    #[test]
+    fn unknown_enum_values_name_the_field() {
+        let mut v = Validator::new();
+        assert_eq!(v.choice::<Rating>("rating", "not rated"), Some(Rating::NotRated));
+        assert_eq!(v.choice::<Rating>("rating", "PG-14"), None);
+        let errors = field_errors(v.into_error());
+        assert!(errors["rating"][0].starts_with("Unknown rating"));
+    }
+
+    #[test]
+    fn columns_come_from_the_configured_list() {
+        let columns: Columns = "Top, Bottom".parse().unwrap();
+        assert_eq!(columns.check("top"), Ok("Top".to_string()));
+        assert!(columns.check("Left").is_err());
+        assert!("Top,,Bottom".parse::<Columns>().is_err());
+        assert!("Top,top".parse::<Columns>().is_err());
+    }
+
+    #[test]
+    fn an_unlisted_column_is_passed_on_with_its_error() {
+        let columns = Columns::default();
+        let mut v = Validator::new();
+
+        assert_eq!(v.kept_column("middle", &columns), ("Middle".to_string(), None));
+        let (column, error) = v.kept_column(" Attic ", &columns);
+        assert_eq!(column, "Attic");
+        assert_eq!(error.as_deref(), Some("Column must be one of Left, Middle, Right"));
+        // Left for the database to check against the movie's current column
+        assert!(v.is_valid());
+
+        v.kept_column("", &columns);
+        assert!(!v.is_valid());
+    }
+
+    #[test]
+    fn an_update_keeps_the_column_error_for_later() {
+        let form = MovieForm {
+            id: "some-id".to_string(),
+            column: "Attic".to_string(),
+            ..valid_movie()
+        };
+        let update = form
+            .into_update(
+                "home".to_string(),
+                Precondition::Any,
+                "test".to_string(),
+                &Columns::default(),
+            )
+            .unwrap();
+        assert_eq!(update.column, "Attic");
+        assert!(update.column_error.is_some());
+
+        let patch = MoviePatchForm {
+            column: Some("Attic".to_string()),
+            ..Default::default()
+        };
+        let patch = patch
+            .into_patch(
+                "home".to_string(),
+                "some-id".to_string(),
+                Precondition::Any,
+                "test".to_string(),
+                &Columns::default(),
+            )
+            .unwrap();
+        assert_eq!(patch.changes.column.as_deref(), Some("Attic"));
+        assert!(patch.column_error.is_some());
+    }
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::{movie, Executor};
+use moviedb::db::libraries::DEFAULT_LIBRARY;
+use moviedb::db::people::{CastEntry, SetCast};
+use moviedb::db::{DbError, Precondition};
+
+fn cast(names: &[&str]) -> Vec<CastEntry> {
+    names
+        .iter()
+        .map(|name| CastEntry {
+            name: name.to_string(),
+            role: None,
+            character: None,
+        })
+        .collect()
+}
+
+#[test]
+fn setting_the_cast_needs_the_current_version() {
+    let mut db = Executor::new("cast");
+    let alien = db
+        .send(movie(DEFAULT_LIBRARY, "Alien", "Sigourney Weaver"))
+        .unwrap()
+        .movie;
+    let set_cast = |names: &[&str], version| SetCast {
+        library: DEFAULT_LIBRARY.to_string(),
+        id: alien.id.clone(),
+        cast: cast(names),
+        precondition: Precondition::Versions(vec![version]),
+        actor: "test".to_string(),
+    };
+
+    let set = db
+        .send(set_cast(&["Sigourney Weaver", "Tom Skerritt"], alien.version))
+        .unwrap();
+    assert_eq!(set.cast.len(), 2);
+    assert_eq!(set.movie.version, alien.version + 1);
+    assert_eq!(set.movie.actors, "Sigourney Weaver, Tom Skerritt");
+
+    // A second client still holding the first version must not win
+    let stale = db.send(set_cast(&["John Hurt"], alien.version));
+    assert!(matches!(stale, Err(DbError::PreconditionFailed(_))));
+}
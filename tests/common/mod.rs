@@ -0,0 +1,100 @@
+//! Helpers shared by the integration tests
+// Each test file uses only some of these
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use actix::prelude::*;
+use actix::SystemRunner;
+use diesel::sqlite::SqliteConnection;
+use moviedb::db::pool::ConnectionOptions;
+use moviedb::db::{self, Aspect, CreateMovie, DbExecutor, Format, Rating};
+
+/// A fresh, migrated database file that is removed again when dropped
+pub struct TempDb(PathBuf);
+
+impl TempDb {
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("moviedb-{}-{}.db", name, process::id()));
+        let db = TempDb(path);
+        db.remove();
+        db::init_db(db.url());
+        db
+    }
+
+    pub fn url(&self) -> &str {
+        self.0.to_str().expect("temp dir is valid UTF-8")
+    }
+
+    fn remove(&self) {
+        for suffix in &["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", self.url(), suffix));
+        }
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+/// A `DbExecutor` on a temporary database, sent messages one at a time the
+/// way the handlers do
+pub struct Executor {
+    system: SystemRunner,
+    addr: Addr<DbExecutor>,
+    // Dropped last, once the executor has let go of its connections
+    _db: TempDb,
+}
+
+impl Executor {
+    pub fn new(name: &str) -> Self {
+        let db = TempDb::new(name);
+        let pool = ConnectionOptions::default().pool(db.url(), 2).unwrap();
+        let system = System::new(name);
+        let addr = SyncArbiter::start(1, move || DbExecutor(pool.clone()));
+        Executor {
+            system,
+            addr,
+            _db: db,
+        }
+    }
+
+    /// A connection of its own to the same database, for checking what the
+    /// executor wrote or writing around it
+    pub fn connection(&self) -> SqliteConnection {
+        ConnectionOptions::default().establish(self._db.url()).unwrap()
+    }
+
+    pub fn send<M>(&mut self, msg: M) -> M::Result
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        DbExecutor: Handler<M>,
+    {
+        self.system
+            .block_on(self.addr.send(msg))
+            .expect("the executor is running")
+    }
+}
+
+/// A valid movie to add to `library`
+pub fn movie(library: &str, title: &str, actors: &str) -> CreateMovie {
+    CreateMovie {
+        library: library.to_string(),
+        id: None,
+        title: title.to_string(),
+        rating: Rating::Pg,
+        category: "Test".to_string(),
+        format: Format::Dvd,
+        aspect: Aspect::Widescreen,
+        actors: actors.to_string(),
+        drawer: "1".to_string(),
+        column: "Left".to_string(),
+        actor: "test".to_string(),
+    }
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::thread;
+
+use common::{movie, TempDb};
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use moviedb::db::pool::{ConnectionOptions, JournalMode};
+use moviedb::db::schema::movies;
+use moviedb::db::libraries::DEFAULT_LIBRARY;
+use moviedb::db::{create_movie, CreateMovie};
+
+const WRITERS: usize = 8;
+const MOVIES_PER_WRITER: usize = 25;
+
+fn writer_movie(writer: usize, n: usize) -> CreateMovie {
+    movie(
+        DEFAULT_LIBRARY,
+        &format!("Writer {} movie {}", writer, n),
+        &format!("Actor {}, Actor {}", writer, n),
+    )
+}
+
+#[test]
+fn pooled_connections_use_wal() {
+    let db = TempDb::new("wal");
+    let pool = ConnectionOptions::default().pool(db.url(), 2).unwrap();
+    let conn = pool.get().unwrap();
+
+    let mode = diesel::select(sql::<Text>("(SELECT journal_mode FROM pragma_journal_mode)"))
+        .get_result::<String>(&*conn)
+        .unwrap();
+    assert_eq!(mode, JournalMode::Wal.as_str());
+    let foreign_keys = diesel::select(sql::<Text>(
+        "(SELECT CAST(foreign_keys AS TEXT) FROM pragma_foreign_keys)",
+    ))
+    .get_result::<String>(&*conn)
+    .unwrap();
+    assert_eq!(foreign_keys, "1");
+}
+
+#[test]
+fn concurrent_writers_do_not_fail() {
+    let db = TempDb::new("writers");
+    let pool = ConnectionOptions::default()
+        .pool(db.url(), WRITERS as u32)
+        .unwrap();
+
+    let writers = (0..WRITERS)
+        .map(|writer| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for n in 0..MOVIES_PER_WRITER {
+                    let conn = pool.get().unwrap();
+                    if let Err(e) = create_movie(&conn, &writer_movie(writer, n)) {
+                        panic!("writer {} failed on movie {}: {}", writer, n, e);
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let conn = pool.get().unwrap();
+    let count = movies::table.count().get_result::<i64>(&*conn).unwrap();
+    assert_eq!(count, (WRITERS * MOVIES_PER_WRITER) as i64);
+}
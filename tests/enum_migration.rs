@@ -0,0 +1,105 @@
+// The diesel 1.3 derives resolve names through the enclosing module
+#![allow(proc_macro_derive_resolution_fallback)]
+
+#[macro_use]
+extern crate diesel;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use moviedb::db::migrations;
+
+/// The enum columns of one movie, read without the current schema
+#[derive(Debug, PartialEq, QueryableByName)]
+struct Spellings {
+    #[sql_type = "Text"]
+    movies_id: String,
+    #[sql_type = "Text"]
+    movies_rating: String,
+    #[sql_type = "Text"]
+    movies_format: String,
+    #[sql_type = "Text"]
+    movies_aspect: String,
+}
+
+impl Spellings {
+    fn new(id: &str, rating: &str, format: &str, aspect: &str) -> Self {
+        Spellings {
+            movies_id: id.to_string(),
+            movies_rating: rating.to_string(),
+            movies_format: format.to_string(),
+            movies_aspect: aspect.to_string(),
+        }
+    }
+}
+
+const LEGACY_MOVIES: &str = "
+    INSERT INTO movies (
+      movies_id, movies_title, movies_rating, movies_format, movies_aspect,
+      movies_category, movies_actors, movies_drawer, movies_column
+    ) VALUES
+      ('1', 'Alien', 'r', 'blu ray', 'Anamorphic Widescreen', 'Test', '', '1', 'Left'),
+      ('2', 'Heat', 'Not Rated', 'BD', 'Pan & Scan', 'Test', '', '1', 'Left'),
+      ('3', 'Ronin', 'R', 'DVD', 'Widescreen', 'Test', '', '1', 'Left'),
+      ('4', 'Zodiac', 'PG-15', 'Betamax', 'Square', 'Test', '', '1', 'Left');
+";
+
+fn migrate_to(conn: &SqliteConnection, version: i32) {
+    let steps = migrations::plan(conn, Some(version)).unwrap();
+    migrations::apply(conn, &steps).unwrap();
+    assert_eq!(migrations::current_version(conn).unwrap(), version);
+}
+
+fn spellings(conn: &SqliteConnection) -> Vec<Spellings> {
+    diesel::sql_query(
+        "SELECT movies_id, movies_rating, movies_format, movies_aspect
+         FROM movies ORDER BY movies_id",
+    )
+    .load(conn)
+    .unwrap()
+}
+
+fn legacy() -> Vec<Spellings> {
+    vec![
+        Spellings::new("1", "r", "blu ray", "Anamorphic Widescreen"),
+        Spellings::new("2", "Not Rated", "BD", "Pan & Scan"),
+        Spellings::new("3", "R", "DVD", "Widescreen"),
+        Spellings::new("4", "PG-15", "Betamax", "Square"),
+    ]
+}
+
+#[test]
+fn legacy_spellings_are_made_canonical_and_put_back() {
+    let conn = SqliteConnection::establish(":memory:").unwrap();
+    migrate_to(&conn, 3);
+    conn.batch_execute(LEGACY_MOVIES).unwrap();
+
+    migrate_to(&conn, 4);
+    assert_eq!(
+        spellings(&conn),
+        vec![
+            Spellings::new("1", "R", "Blu-ray", "Widescreen"),
+            Spellings::new("2", "NR", "Blu-ray", "Fullscreen"),
+            Spellings::new("3", "R", "DVD", "Widescreen"),
+            Spellings::new("4", "NR", "Other", "Other"),
+        ]
+    );
+
+    migrate_to(&conn, 3);
+    assert_eq!(spellings(&conn), legacy());
+}
+
+#[test]
+fn going_down_and_up_again_gives_the_same_result() {
+    let conn = SqliteConnection::establish(":memory:").unwrap();
+    migrate_to(&conn, 3);
+    conn.batch_execute(LEGACY_MOVIES).unwrap();
+
+    migrate_to(&conn, 4);
+    let canonical = spellings(&conn);
+    migrate_to(&conn, 3);
+    migrate_to(&conn, 4);
+    assert_eq!(spellings(&conn), canonical);
+    migrate_to(&conn, 3);
+    assert_eq!(spellings(&conn), legacy());
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{movie, Executor};
+use moviedb::db::import::{ImportMovies, Row};
+use moviedb::db::libraries::DEFAULT_LIBRARY;
+use moviedb::db::{CreateMovie, DbError, GetAllMovies, MovieFilter};
+
+fn row(line: u64, title: &str) -> Row {
+    Row {
+        line,
+        movie: Ok(movie(DEFAULT_LIBRARY, title, "")),
+    }
+}
+
+fn saved_titles(db: &mut Executor) -> Vec<String> {
+    let all = GetAllMovies {
+        library: DEFAULT_LIBRARY.to_string(),
+        filter: MovieFilter::default(),
+    };
+    db.send(all).unwrap().into_iter().map(|m| m.title).collect()
+}
+
+fn failed_lines(e: &DbError) -> Vec<u64> {
+    let rows = e.details().unwrap()["rows"].as_array().unwrap().clone();
+    rows.iter().map(|r| r["line"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn a_row_that_fails_validation_keeps_the_others_out() {
+    let mut db = Executor::new("import_invalid_row");
+    let rows = vec![
+        row(2, "Alien"),
+        Row {
+            line: 3,
+            movie: Err(DbError::field("rating", "unknown rating `PG-15`")),
+        },
+        row(4, "Aliens"),
+    ];
+
+    let e = db.send(ImportMovies { rows, dry_run: false }).unwrap_err();
+
+    assert!(matches!(e, DbError::Validation { .. }));
+    assert_eq!(failed_lines(&e), vec![3]);
+    assert!(saved_titles(&mut db).is_empty());
+}
+
+#[test]
+fn a_row_the_database_refuses_rolls_back_the_rows_before_it() {
+    let mut db = Executor::new("import_conflicting_row");
+    let id = "5f0c3a34-8f6a-4d38-9a43-1e2f7d0c9b11".to_string();
+    let with_id = |title: &str| CreateMovie {
+        id: Some(id.clone()),
+        ..movie(DEFAULT_LIBRARY, title, "")
+    };
+    let rows = vec![
+        row(2, "Alien"),
+        Row { line: 3, movie: Ok(with_id("Heat")) },
+        Row { line: 4, movie: Ok(with_id("Ronin")) },
+    ];
+
+    let e = db.send(ImportMovies { rows, dry_run: false }).unwrap_err();
+
+    assert_eq!(failed_lines(&e), vec![4]);
+    assert!(saved_titles(&mut db).is_empty());
+}
+
+#[test]
+fn a_dry_run_reports_the_rows_and_saves_nothing() {
+    let mut db = Executor::new("import_dry_run");
+    let rows = vec![row(2, "Alien"), row(3, "Aliens")];
+
+    let report = db.send(ImportMovies { rows, dry_run: true }).unwrap();
+
+    assert_eq!(report.created, 2);
+    assert!(saved_titles(&mut db).is_empty());
+
+    let rows = vec![row(2, "Alien"), row(3, "Aliens")];
+    db.send(ImportMovies { rows, dry_run: false }).unwrap();
+    assert_eq!(saved_titles(&mut db).len(), 2);
+}
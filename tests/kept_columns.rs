@@ -0,0 +1,64 @@
+mod common;
+
+use common::{movie, Executor};
+use moviedb::db::libraries::DEFAULT_LIBRARY;
+use moviedb::db::{model, CreateMovie, DbError, Precondition};
+use moviedb::validation::{Columns, MovieForm};
+
+fn add(db: &mut Executor, title: &str, column: &str) -> model::Movie {
+    let new_movie = CreateMovie {
+        column: column.to_string(),
+        ..movie(DEFAULT_LIBRARY, title, "")
+    };
+    db.send(new_movie).unwrap().movie
+}
+
+fn move_to(
+    db: &mut Executor,
+    movie: &model::Movie,
+    column: &str,
+) -> Result<model::Movie, DbError> {
+    let form = MovieForm {
+        id: movie.id.clone(),
+        title: movie.title.clone(),
+        rating: movie.rating.to_string(),
+        category: movie.category.clone(),
+        format: movie.format.to_string(),
+        aspect: movie.aspect.to_string(),
+        actors: movie.actors.clone(),
+        drawer: movie.drawer.clone(),
+        column: column.to_string(),
+    };
+    let update = form.into_update(
+        DEFAULT_LIBRARY.to_string(),
+        Precondition::Any,
+        "test".to_string(),
+        &Columns::default(),
+    )?;
+    db.send(update)
+}
+
+#[test]
+fn a_movie_may_stay_in_a_column_no_longer_configured() {
+    let mut db = Executor::new("kept-column");
+    let attic = add(&mut db, "Alien", "Attic");
+
+    let kept = move_to(&mut db, &attic, "Attic").unwrap();
+    assert_eq!(kept.column, "Attic");
+    assert_eq!(kept.version, attic.version + 1);
+}
+
+#[test]
+fn no_movie_may_move_into_a_column_no_longer_configured() {
+    let mut db = Executor::new("moved-column");
+    let attic = add(&mut db, "Alien", "Attic");
+    let shelf = add(&mut db, "Aliens", "Left");
+
+    let moved = move_to(&mut db, &attic, "Basement");
+    assert!(matches!(moved, Err(DbError::Validation { .. })));
+    let moved = move_to(&mut db, &shelf, "Attic");
+    assert!(matches!(moved, Err(DbError::Validation { .. })));
+
+    // Moving out of the old column into a configured one is fine
+    assert_eq!(move_to(&mut db, &attic, "right").unwrap().column, "Right");
+}
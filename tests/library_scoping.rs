@@ -0,0 +1,116 @@
+mod common;
+
+use common::{movie, Executor};
+use moviedb::db::libraries::{CreateLibrary, OpenLibrary, DEFAULT_LIBRARY};
+use moviedb::db::model::User;
+use moviedb::db::people::{GetPeople, GetPerson};
+use moviedb::db::revisions::GetRevisions;
+use moviedb::db::users::CreateUser;
+use moviedb::db::{DbError, GetMovie, Role, SearchMovies};
+
+fn user(db: &mut Executor, username: &str) -> User {
+    db.send(CreateUser {
+        username: username.to_string(),
+        password: "correct horse".to_string(),
+        role: Role::Editor,
+    })
+    .unwrap()
+}
+
+fn is_not_found<T>(res: Result<T, DbError>) -> bool {
+    matches!(res, Err(DbError::NotFound(_)))
+}
+
+#[test]
+fn other_libraries_are_hidden_from_non_members() {
+    let mut db = Executor::new("scoping");
+    let alice = user(&mut db, "alice");
+    let bob = user(&mut db, "bob");
+    let cabin = db
+        .send(CreateLibrary {
+            name: "Cabin".to_string(),
+            user_id: alice.id.clone(),
+        })
+        .unwrap();
+    let hidden = db
+        .send(movie(&cabin.id, "Cabin Fever", "Rider Strong"))
+        .unwrap()
+        .movie;
+    db.send(movie(DEFAULT_LIBRARY, "Home Alone", "Macaulay Culkin"))
+        .unwrap();
+
+    // Opening someone else's library looks the same as one that is not there
+    let opened = db.send(OpenLibrary {
+        library: Some(cabin.id.clone()),
+        user: bob.clone(),
+    });
+    assert!(is_not_found(opened));
+    let opened = db.send(OpenLibrary {
+        library: Some(cabin.id.clone()),
+        user: alice.clone(),
+    });
+    assert_eq!(opened.unwrap().id, cabin.id);
+    let own = db.send(OpenLibrary {
+        library: None,
+        user: alice,
+    });
+    assert_eq!(own.unwrap().id, cabin.id);
+
+    // Nor does anything in it show through the library bob can open
+    let home = DEFAULT_LIBRARY.to_string();
+    let found = db.send(GetMovie {
+        library: home.clone(),
+        id: hidden.id.clone(),
+    });
+    assert!(is_not_found(found));
+
+    let hits = db
+        .send(SearchMovies {
+            library: home.clone(),
+            q: "cabin".to_string(),
+            limit: None,
+        })
+        .unwrap();
+    assert!(hits.is_empty());
+    let hits = db
+        .send(SearchMovies {
+            library: cabin.id.clone(),
+            q: "cabin".to_string(),
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+
+    let people = db
+        .send(GetPeople {
+            library: cabin.id.clone(),
+            name: None,
+            limit: None,
+            offset: None,
+        })
+        .unwrap();
+    let rider = people
+        .iter()
+        .find(|person| person.name == "Rider Strong")
+        .expect("the cabin movie's actor is listed in the cabin");
+    let people = db
+        .send(GetPeople {
+            library: home.clone(),
+            name: None,
+            limit: None,
+            offset: None,
+        })
+        .unwrap();
+    assert!(people.iter().all(|person| person.name != "Rider Strong"));
+    let person = db.send(GetPerson {
+        library: home.clone(),
+        id: rider.id.clone(),
+    });
+    assert!(is_not_found(person));
+
+    let revisions = db.send(GetRevisions {
+        library: home,
+        id: hidden.id,
+    });
+    assert!(is_not_found(revisions));
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use chrono::{Duration, NaiveDate};
+use common::{movie, Executor};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use moviedb::db::audit::GetAudit;
+use moviedb::db::libraries::DEFAULT_LIBRARY;
+use moviedb::db::loans::{CheckIn, CheckOut, GetLoans};
+use moviedb::db::schema::loans;
+use moviedb::db::{model, now, AuditAction, DbError, GetMovie};
+
+fn lend(
+    db: &mut Executor,
+    movie: &model::Movie,
+    due_date: NaiveDate,
+) -> Result<model::Loan, DbError> {
+    db.send(CheckOut {
+        library: DEFAULT_LIBRARY.to_string(),
+        movie_id: movie.id.clone(),
+        borrower: "Bob".to_string(),
+        contact: String::new(),
+        due_date,
+        actor: "test".to_string(),
+    })
+}
+
+fn take_back(db: &mut Executor, movie: &model::Movie) -> Result<model::Loan, DbError> {
+    db.send(CheckIn {
+        library: DEFAULT_LIBRARY.to_string(),
+        movie_id: movie.id.clone(),
+        actor: "test".to_string(),
+    })
+}
+
+fn reload(db: &mut Executor, movie: &model::Movie) -> model::Movie {
+    db.send(GetMovie {
+        library: DEFAULT_LIBRARY.to_string(),
+        id: movie.id.clone(),
+    })
+    .unwrap()
+}
+
+fn add(db: &mut Executor, title: &str) -> model::Movie {
+    db.send(movie(DEFAULT_LIBRARY, title, "")).unwrap().movie
+}
+
+fn next_week() -> NaiveDate {
+    now().date() + Duration::weeks(1)
+}
+
+#[test]
+fn lending_and_returning_are_new_versions_in_the_audit_log() {
+    let mut db = Executor::new("loan-versions");
+    let alien = add(&mut db, "Alien");
+
+    lend(&mut db, &alien, next_week()).unwrap();
+    let lent = reload(&mut db, &alien);
+    assert!(lent.on_loan);
+    assert_eq!(lent.version, alien.version + 1);
+
+    take_back(&mut db, &alien).unwrap();
+    let returned = reload(&mut db, &alien);
+    assert!(!returned.on_loan);
+    assert_eq!(returned.version, lent.version + 1);
+    assert!(returned.updated_at >= lent.updated_at);
+
+    let audit = db
+        .send(GetAudit {
+            library: DEFAULT_LIBRARY.to_string(),
+            movie: Some(alien.id.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+    let actions: Vec<AuditAction> = audit.iter().map(|entry| entry.action).collect();
+    assert_eq!(
+        actions,
+        [AuditAction::CheckIn, AuditAction::CheckOut, AuditAction::Create]
+    );
+}
+
+#[test]
+fn a_movie_can_only_be_lent_once_at_a_time() {
+    let mut db = Executor::new("double-loan");
+    let alien = add(&mut db, "Alien");
+
+    let first = lend(&mut db, &alien, next_week()).unwrap();
+    let second = lend(&mut db, &alien, next_week());
+    assert!(matches!(second, Err(DbError::Conflict(_))));
+
+    // The database holds to it too, whatever the handler checks
+    let conn = db.connection();
+    let duplicate = model::Loan {
+        id: "another".to_string(),
+        ..first
+    };
+    let inserted = diesel::insert_into(loans::table)
+        .values(&duplicate)
+        .execute(&conn);
+    assert!(matches!(
+        inserted,
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _))
+    ));
+
+    // Once it is back it can go out again
+    take_back(&mut db, &alien).unwrap();
+    assert!(lend(&mut db, &alien, next_week()).is_ok());
+}
+
+#[test]
+fn only_a_lent_movie_can_be_returned() {
+    let mut db = Executor::new("return");
+    let alien = add(&mut db, "Alien");
+
+    assert!(matches!(take_back(&mut db, &alien), Err(DbError::NotFound(_))));
+    lend(&mut db, &alien, next_week()).unwrap();
+    assert!(take_back(&mut db, &alien).is_ok());
+    assert!(matches!(take_back(&mut db, &alien), Err(DbError::NotFound(_))));
+    assert_eq!(reload(&mut db, &alien).version, alien.version + 2);
+}
+
+#[test]
+fn loans_are_overdue_once_their_due_date_has_passed() {
+    let mut db = Executor::new("overdue");
+    let today = now().date();
+    let late = add(&mut db, "Late");
+    let due = add(&mut db, "Due today");
+    let early = add(&mut db, "Early");
+    lend(&mut db, &late, today - Duration::days(1)).unwrap();
+    lend(&mut db, &due, today).unwrap();
+    lend(&mut db, &early, next_week()).unwrap();
+
+    let loans = |db: &mut Executor, overdue| {
+        db.send(GetLoans {
+            library: DEFAULT_LIBRARY.to_string(),
+            overdue,
+            ..Default::default()
+        })
+        .unwrap()
+        .into_iter()
+        .map(|open| open.movie.title)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(loans(&mut db, true), ["Late"]);
+    assert_eq!(loans(&mut db, false), ["Late", "Due today", "Early"]);
+}
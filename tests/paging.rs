@@ -0,0 +1,129 @@
+mod common;
+
+use common::{movie, Executor};
+use moviedb::db::libraries::DEFAULT_LIBRARY;
+use moviedb::db::{DbError, ListMovies, MovieFilter, MoviePage, Order, SortBy};
+
+/// Three movies share a title and two more share another, so most page
+/// boundaries fall between movies with equal titles
+const TITLES: [&str; 7] = ["Heat", "Alien", "Zodiac", "Alien", "Ronin", "Heat", "Alien"];
+
+fn library(name: &str) -> Executor {
+    let mut db = Executor::new(name);
+    for title in TITLES.iter() {
+        db.send(movie(DEFAULT_LIBRARY, title, "")).unwrap();
+    }
+    db
+}
+
+fn first_page(order: Order) -> ListMovies {
+    ListMovies {
+        library: DEFAULT_LIBRARY.to_string(),
+        filter: MovieFilter {
+            order: Some(order),
+            ..MovieFilter::default()
+        },
+        limit: Some(2),
+        offset: None,
+        cursor: None,
+    }
+}
+
+fn ids(page: &MoviePage) -> Vec<(String, String)> {
+    page.items.iter().map(|m| (m.title.clone(), m.id.clone())).collect()
+}
+
+/// Follow `next` links from the first page, returning every page in order
+fn walk_forward(db: &mut Executor, first: ListMovies) -> Vec<MoviePage> {
+    let mut pages = vec![db.send(first).unwrap()];
+    while let Some(next) = pages.last().unwrap().next.clone() {
+        pages.push(db.send(next).unwrap());
+    }
+    pages
+}
+
+#[test]
+fn every_movie_is_listed_once_in_order() {
+    let mut db = library("paging_forward");
+
+    let pages = walk_forward(&mut db, first_page(Order::Asc));
+    let listed: Vec<_> = pages.iter().flat_map(ids).collect();
+
+    let mut expected = listed.clone();
+    expected.sort();
+    expected.dedup();
+    assert_eq!(listed, expected);
+    assert_eq!(listed.len(), TITLES.len());
+    assert!(pages.iter().all(|page| page.total == TITLES.len() as i64));
+}
+
+#[test]
+fn descending_pages_list_every_movie_once() {
+    let mut db = library("paging_descending");
+
+    let pages = walk_forward(&mut db, first_page(Order::Desc));
+    let listed: Vec<_> = pages.iter().flat_map(ids).collect();
+
+    let titles: Vec<_> = listed.iter().map(|(title, _)| title.as_str()).collect();
+    assert_eq!(titles, ["Zodiac", "Ronin", "Heat", "Heat", "Alien", "Alien", "Alien"]);
+    let mut unique = listed.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), TITLES.len());
+}
+
+#[test]
+fn the_last_page_has_no_next_link_and_leads_back() {
+    let mut db = library("paging_last_page");
+
+    let pages = walk_forward(&mut db, first_page(Order::Asc));
+    let last = pages.last().unwrap();
+    assert_eq!(pages.len(), 4);
+    assert_eq!(last.items.len(), 1);
+    assert!(last.next.is_none());
+    assert!(pages[0].prev.is_none());
+
+    let mut page = last.clone();
+    for expected in pages.iter().rev().skip(1) {
+        page = db.send(page.prev.clone().unwrap()).unwrap();
+        assert_eq!(ids(&page), ids(expected));
+    }
+    assert!(page.prev.is_none());
+}
+
+#[test]
+fn a_malformed_cursor_is_rejected() {
+    let mut db = library("paging_bad_cursor");
+
+    for cursor in &["not a cursor!", "", "eA", "YR9BbGllbg"] {
+        let list = ListMovies {
+            cursor: Some(cursor.to_string()),
+            ..first_page(Order::Asc)
+        };
+        match db.send(list) {
+            Err(DbError::Validation { message, .. }) => assert_eq!(message, "Invalid cursor"),
+            other => panic!("cursor {:?} gave {:?}", cursor, other),
+        }
+    }
+}
+
+#[test]
+fn a_cursor_needs_the_title_sort() {
+    let mut db = library("paging_other_sort");
+    let next = db.send(first_page(Order::Asc)).unwrap().next.unwrap();
+
+    let by_rating = ListMovies {
+        filter: MovieFilter {
+            sort: Some(SortBy::Rating),
+            ..next.filter.clone()
+        },
+        ..next.clone()
+    };
+    let with_offset = ListMovies {
+        offset: Some(2),
+        ..next
+    };
+
+    assert!(matches!(db.send(by_rating), Err(DbError::Validation { .. })));
+    assert!(matches!(db.send(with_offset), Err(DbError::Validation { .. })));
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use chrono::Duration;
+use common::{movie, Executor};
+use moviedb::db::libraries::DEFAULT_LIBRARY;
+use moviedb::db::revisions::{GetRevisions, RevertMovie};
+use moviedb::db::{self, model, trash, CreateMovie, DbError, DeleteMovie, GetMovie, Precondition};
+use moviedb::validation::Columns;
+
+fn add(db: &mut Executor, title: &str, column: &str) -> model::Movie {
+    let new_movie = CreateMovie {
+        column: column.to_string(),
+        ..movie(DEFAULT_LIBRARY, title, "")
+    };
+    db.send(new_movie).unwrap().movie
+}
+
+fn revert(
+    db: &mut Executor,
+    movie: &model::Movie,
+    version: i32,
+    columns: &str,
+) -> Result<model::Movie, DbError> {
+    db.send(RevertMovie {
+        library: DEFAULT_LIBRARY.to_string(),
+        id: movie.id.clone(),
+        version,
+        columns: columns.parse::<Columns>().unwrap(),
+        actor: "test".to_string(),
+    })
+}
+
+fn delete(db: &mut Executor, movie: &model::Movie) {
+    db.send(DeleteMovie {
+        library: DEFAULT_LIBRARY.to_string(),
+        id: movie.id.clone(),
+        precondition: Precondition::Any,
+        actor: "test".to_string(),
+    })
+    .unwrap();
+}
+
+fn purge_trash(db: &mut Executor) {
+    let cutoff = db::now() + Duration::seconds(1);
+    trash::purge(&db.connection(), cutoff, "test").unwrap();
+}
+
+fn newest_revision(db: &mut Executor, movie: &model::Movie) -> i32 {
+    let revisions = db
+        .send(GetRevisions {
+            library: DEFAULT_LIBRARY.to_string(),
+            id: movie.id.clone(),
+        })
+        .unwrap();
+    revisions[0].version
+}
+
+#[test]
+fn reverting_a_purged_movie_recreates_it_as_a_new_version() {
+    let mut db = Executor::new("revert_purged");
+    let alien = add(&mut db, "Alien", "Left");
+    delete(&mut db, &alien);
+    purge_trash(&mut db);
+    let get = || GetMovie {
+        library: DEFAULT_LIBRARY.to_string(),
+        id: alien.id.clone(),
+    };
+    assert!(matches!(db.send(get()), Err(DbError::NotFound(_))));
+    let before = newest_revision(&mut db, &alien);
+
+    let reverted = revert(&mut db, &alien, alien.version, "Left,Middle,Right").unwrap();
+
+    assert_eq!(reverted.title, "Alien");
+    assert_eq!(reverted.version, before + 1);
+    assert!(reverted.version > alien.version);
+    assert_eq!(reverted.deleted_at, None);
+    assert_eq!(db.send(get()).unwrap(), reverted);
+    assert_eq!(newest_revision(&mut db, &alien), reverted.version);
+}
+
+#[test]
+fn a_purged_movie_cannot_come_back_into_an_unlisted_column() {
+    let mut db = Executor::new("revert_purged_column");
+    let alien = add(&mut db, "Alien", "Right");
+    delete(&mut db, &alien);
+    purge_trash(&mut db);
+
+    let e = revert(&mut db, &alien, alien.version, "Left,Middle").unwrap_err();
+    assert!(e.details().unwrap().get("column").is_some());
+
+    let reverted = revert(&mut db, &alien, alien.version, "left,Right").unwrap();
+    assert_eq!(reverted.column, "Right");
+}
+
+#[test]
+fn a_movie_can_be_reverted_in_the_unlisted_column_it_is_in() {
+    let mut db = Executor::new("revert_kept_column");
+    let alien = add(&mut db, "Alien", "Right");
+    delete(&mut db, &alien);
+
+    let reverted = revert(&mut db, &alien, alien.version, "Left,Middle").unwrap();
+    assert_eq!(reverted.column, "Right");
+    assert_eq!(reverted.deleted_at, None);
+}
+
+#[test]
+fn the_revisions_column_takes_the_configured_spelling() {
+    let mut db = Executor::new("revert_column_spelling");
+    let alien = add(&mut db, "Alien", "left");
+    delete(&mut db, &alien);
+    purge_trash(&mut db);
+
+    let reverted = revert(&mut db, &alien, alien.version, "Left,Middle,Right").unwrap();
+    assert_eq!(reverted.column, "Left");
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{movie, Executor};
+use moviedb::db::libraries::DEFAULT_LIBRARY;
+use moviedb::db::{DbError, SearchMovies};
+
+fn library(name: &str) -> Executor {
+    let mut db = Executor::new(name);
+    let movies = [
+        ("Alien", "Sigourney Weaver, Tom Skerritt"),
+        ("Aliens", "Sigourney Weaver, Michael Biehn"),
+        ("Heat", "Al Pacino, Robert De Niro"),
+        ("The Good, the Bad and the Ugly", "Clint Eastwood"),
+        ("Léon: The Professional", "Jean Reno, Natalie Portman"),
+    ];
+    for (title, actors) in movies.iter() {
+        db.send(movie(DEFAULT_LIBRARY, title, actors)).unwrap();
+    }
+    db
+}
+
+fn search(db: &mut Executor, q: &str) -> Result<Vec<String>, DbError> {
+    let hits = db.send(SearchMovies {
+        library: DEFAULT_LIBRARY.to_string(),
+        q: q.to_string(),
+        limit: None,
+    })?;
+    Ok(hits.into_iter().map(|hit| hit.movie.title).collect())
+}
+
+fn titles(db: &mut Executor, q: &str) -> Vec<String> {
+    let mut titles =
+        search(db, q).unwrap_or_else(|e| panic!("searching for {:?} failed: {:?}", q, e));
+    titles.sort();
+    titles
+}
+
+#[test]
+fn words_match_as_prefixes_in_any_order() {
+    let mut db = library("search_prefixes");
+
+    assert_eq!(titles(&mut db, "alie"), ["Alien", "Aliens"]);
+    assert_eq!(titles(&mut db, "weaver ALIENS"), ["Aliens"]);
+    assert_eq!(titles(&mut db, "de nir"), ["Heat"]);
+    assert_eq!(titles(&mut db, "leon prof"), ["Léon: The Professional"]);
+    assert!(titles(&mut db, "alien heat").is_empty());
+}
+
+#[test]
+fn operators_in_the_query_are_plain_words() {
+    let mut db = library("search_operators");
+
+    assert_eq!(titles(&mut db, "good AND bad"), ["The Good, the Bad and the Ugly"]);
+    assert_eq!(titles(&mut db, "bad ugly NOT"), Vec::<String>::new());
+    assert!(titles(&mut db, "alien OR heat").is_empty());
+    assert!(titles(&mut db, "NEAR(alien heat)").is_empty());
+    assert!(titles(&mut db, "movies_title:alien").is_empty());
+    assert_eq!(titles(&mut db, "^alien"), ["Alien", "Aliens"]);
+}
+
+#[test]
+fn punctuation_is_searched_or_ignored_rather_than_failing() {
+    let mut db = library("search_punctuation");
+
+    for q in &[
+        "\"alien",
+        "alien\"",
+        "\"sigourney weaver\"",
+        "al*en",
+        "*",
+        "alien*",
+        "(",
+        "alien)",
+        "+-",
+        "{movies_title}: alien",
+        "\"\"",
+        "'",
+        "alien; DROP TABLE movies",
+    ] {
+        assert!(search(&mut db, q).is_ok(), "searching for {:?} failed", q);
+    }
+    assert_eq!(titles(&mut db, "\"sigourney weaver\""), ["Alien", "Aliens"]);
+    assert_eq!(titles(&mut db, "alien*"), ["Alien", "Aliens"]);
+    assert_eq!(titles(&mut db, "Good,"), ["The Good, the Bad and the Ugly"]);
+    assert!(titles(&mut db, "\"\"").is_empty());
+    assert!(titles(&mut db, "*").is_empty());
+}
+
+#[test]
+fn a_blank_query_is_rejected() {
+    let mut db = library("search_blank");
+
+    assert!(matches!(search(&mut db, ""), Err(DbError::Validation { .. })));
+    assert!(matches!(search(&mut db, "  \t"), Err(DbError::Validation { .. })));
+}
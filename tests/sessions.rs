@@ -0,0 +1,73 @@
+mod common;
+
+use chrono::Duration;
+use common::Executor;
+use moviedb::db::users::{Authenticate, CreateUser, Login, Logout};
+use moviedb::db::{DbError, Role};
+
+fn login(
+    db: &mut Executor,
+    username: &str,
+    password: &str,
+    ttl: Duration,
+) -> Result<String, DbError> {
+    db.send(Login {
+        username: username.to_string(),
+        password: password.to_string(),
+        ttl,
+    })
+    .map(|logged_in| logged_in.token)
+}
+
+fn is_unauthorized<T>(res: Result<T, DbError>) -> bool {
+    matches!(res, Err(DbError::Unauthorized(_)))
+}
+
+fn with_user(name: &str) -> Executor {
+    let mut db = Executor::new(name);
+    db.send(CreateUser {
+        username: "alice".to_string(),
+        password: "correct horse".to_string(),
+        role: Role::Viewer,
+    })
+    .unwrap();
+    db
+}
+
+#[test]
+fn wrong_passwords_and_unknown_users_are_turned_away() {
+    let mut db = with_user("login");
+    let day = Duration::days(1);
+
+    assert!(is_unauthorized(login(&mut db, "alice", "battery staple", day)));
+    assert!(is_unauthorized(login(&mut db, "mallory", "correct horse", day)));
+    assert!(login(&mut db, "alice", "correct horse", day).is_ok());
+}
+
+#[test]
+fn a_session_lasts_until_logout() {
+    let mut db = with_user("logout");
+
+    let token = login(&mut db, "alice", "correct horse", Duration::days(1)).unwrap();
+    let user = db.send(Authenticate { token: token.clone() });
+    assert_eq!(user.unwrap().username, "alice");
+
+    db.send(Logout { token: token.clone() }).unwrap();
+    assert!(is_unauthorized(db.send(Authenticate { token })));
+}
+
+#[test]
+fn expired_sessions_are_turned_away() {
+    let mut db = with_user("expiry");
+
+    let token = login(&mut db, "alice", "correct horse", Duration::seconds(-1)).unwrap();
+    assert!(is_unauthorized(db.send(Authenticate { token })));
+}
+
+#[test]
+fn sessions_ending_past_the_last_timestamp_are_refused() {
+    let mut db = with_user("ttl");
+
+    let res = login(&mut db, "alice", "correct horse", Duration::max_value());
+    assert!(matches!(res, Err(DbError::Validation { .. })));
+}
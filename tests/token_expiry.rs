@@ -0,0 +1,36 @@
+use moviedb::cli::parse_age;
+use moviedb::db::DbError;
+use moviedb::validation::TokenForm;
+
+fn token_form(expires_in: &str) -> TokenForm {
+    TokenForm {
+        name: "backup script".to_string(),
+        read_only: true,
+        expires_in: Some(expires_in.to_string()),
+    }
+}
+
+#[test]
+fn ages_past_the_range_of_a_duration_are_rejected() {
+    assert!(parse_age("90d").is_ok());
+    assert!(parse_age("9223372036854775807w").is_err());
+    assert!(parse_age("999999999999999d").is_err());
+}
+
+#[test]
+fn a_very_large_expiry_is_a_validation_error() {
+    match token_form("999999999d").into_create("user".to_string()) {
+        Err(DbError::Validation { details, .. }) => {
+            assert!(details.unwrap()["expires_in"].is_array());
+        }
+        other => panic!("expected a validation error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn a_reasonable_expiry_is_accepted() {
+    let token = token_form("90d")
+        .into_create("user".to_string())
+        .unwrap_or_else(|e| panic!("{}", e));
+    assert!(token.expires_at.is_some());
+}